@@ -0,0 +1,206 @@
+use anyhow::{anyhow, Context, Result};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use crate::checksum::sha256_hex;
+use crate::config::{add_cli_tool, load_cli_tools, resolve_tool, CliTool, CliToolsConfig};
+use crate::downloader::Downloader;
+use crate::github::{get_latest_release, Release};
+use crate::install::{get_install_dir, install_binary};
+use crate::platform::{find_appropriate_asset, preferred_libc, Platform};
+use crate::preflight::preflight_asset;
+use crate::report::{info, report, warning, Event};
+
+const MANIFEST_PATH: &str = "bundle.json";
+const CATALOG_PATH: &str = "cli-tools.json";
+const FORMAT_VERSION: u32 = 1;
+
+/// Description of the content of a bundle, stored as `bundle.json` in the archive
+#[derive(Debug, Deserialize, Serialize)]
+pub struct BundleManifest {
+    pub format_version: u32,
+    pub tools: Vec<BundledTool>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct BundledTool {
+    pub name: String,
+    pub repo: String,
+    /// Release metadata as returned by the GitHub API
+    pub release: Release,
+    pub files: Vec<BundledFile>,
+}
+
+/// Asset downloaded for one target
+#[derive(Debug, Deserialize, Serialize)]
+pub struct BundledFile {
+    pub target: String,
+    pub asset: String,
+    /// Path of the asset in the archive
+    pub path: String,
+    pub sha256: String,
+    pub size: u64,
+    /// URL the asset was downloaded from, a mirror or the original URL
+    pub source_url: String,
+}
+
+/// Resolve the latest release of each tool, download its asset for every target,
+/// and write everything needed to install them offline into a `.tar.gz` bundle.
+pub async fn create_bundle(tools: &[String], targets: &[Platform], output: &Path) -> Result<()> {
+    let downloader = Downloader::default();
+    let mut manifest = BundleManifest { format_version: FORMAT_VERSION, tools: Vec::new() };
+    let mut catalog = CliToolsConfig { tools: Vec::new() };
+    let mut assets: Vec<(String, Vec<u8>)> = Vec::new();
+
+    for tool in tools {
+        let cli_tool = resolve_tool(tool)?;
+        // Catalog names may differ from the binary, which is named after the repository as on install
+        let name = cli_tool.repo.split('/').next_back().unwrap().to_string();
+        report(Event::Resolving { tool: name.clone(), repo: cli_tool.repo.clone() });
+        let release = get_latest_release(&cli_tool.repo).await?;
+        report(Event::Resolved { tool: name.clone(), version: release.tag_name.clone() });
+
+        let mut files = Vec::new();
+        for target in targets {
+            let asset = find_appropriate_asset(&release, &name, target)
+                .with_context(|| format!("{} has no asset for {}", name, target))?;
+            report(Event::AssetSelected { tool: name.clone(), asset: asset.name.clone(), size: asset.size });
+
+            let download = downloader.download_file(&asset.browser_download_url, asset.size).await?;
+            let path = format!("assets/{}/{}/{}", name, target, asset.name);
+            files.push(BundledFile {
+                target: target.to_string(),
+                asset: asset.name.clone(),
+                path: path.clone(),
+                sha256: sha256_hex(&download.data),
+                size: download.data.len() as u64,
+                source_url: download.source_url,
+            });
+            assets.push((path, download.data));
+        }
+
+        manifest.tools.push(BundledTool {
+            name,
+            repo: cli_tool.repo.clone(),
+            release,
+            files,
+        });
+        catalog.tools.push(cli_tool);
+    }
+
+    let file = File::create(output)
+        .with_context(|| format!("Failed to create {}", output.display()))?;
+    let mut builder = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+    append_file(&mut builder, MANIFEST_PATH, &serde_json::to_vec_pretty(&manifest)?)?;
+    append_file(&mut builder, CATALOG_PATH, &serde_json::to_vec_pretty(&catalog)?)?;
+    for (path, data) in &assets {
+        append_file(&mut builder, path, data)?;
+    }
+    builder.into_inner()?.finish()?;
+
+    info(format!("Created bundle {} with {} tool(s) for {}", output.display(), manifest.tools.len(),
+        targets.iter().map(|t| t.to_string()).collect::<Vec<_>>().join(", ")));
+
+    Ok(())
+}
+
+/// Install the tools of a bundle for this platform, without any network access
+pub fn install_bundle(bundle: &Path, dir: Option<&PathBuf>) -> Result<()> {
+    let mut entries = read_bundle(bundle)?;
+
+    let manifest: BundleManifest = serde_json::from_slice(
+        &entries.remove(MANIFEST_PATH).ok_or_else(|| anyhow!("{} is not a coolclis bundle", bundle.display()))?,
+    )?;
+    if manifest.format_version > FORMAT_VERSION {
+        return Err(anyhow!("Unsupported bundle format version {}", manifest.format_version));
+    }
+
+    let host = Platform::host();
+    let (preferred, _) = preferred_libc(&host);
+    let install_dir = get_install_dir(dir)?;
+    let mut installed = 0;
+
+    for tool in &manifest.tools {
+        let name = tool.repo.split('/').next_back().unwrap();
+        // Targets may name a libc, the preferred one is used if both were bundled
+        let file = tool.files.iter()
+            .filter_map(|f| Platform::parse(&f.target).ok().filter(|t| t.is_host()).map(|t| (f, t.libc)))
+            .min_by_key(|(_, libc)| match libc {
+                Some(libc) if *libc == preferred => 0,
+                None => 1,
+                Some(_) => 2,
+            })
+            .map(|(f, _)| f);
+        let Some(file) = file else {
+            warning(format!("Skipping {}: not bundled for {} (bundled for {})", name, host,
+                tool.files.iter().map(|f| f.target.as_str()).collect::<Vec<_>>().join(", ")));
+            continue;
+        };
+
+        let data = entries.get(&file.path)
+            .ok_or_else(|| anyhow!("Bundle is missing {}", file.path))?;
+        if sha256_hex(data) != file.sha256 {
+            return Err(anyhow!("Checksum mismatch for {} in the bundle", file.path));
+        }
+
+        if let Err(e) = preflight_asset(name, &file.asset, data) {
+            warning(format!("Skipping {}: {} can't run on this system: {:#}", name, file.asset, e));
+            continue;
+        }
+
+        info(format!("Installing {} {} from bundle", name, tool.release.tag_name));
+        let file_path = install_binary(name, &tool.repo, &tool.release.tag_name, &file.asset, data, &install_dir)?;
+        report(Event::Installed { tool: name.to_string(), path: file_path });
+        installed += 1;
+    }
+
+    // Make the bundled tools known to the catalog as well
+    if let Some(catalog) = entries.get(CATALOG_PATH) {
+        let catalog: CliToolsConfig = serde_json::from_slice(catalog)?;
+        let known = load_cli_tools()?;
+        for CliTool { name, repo, description, .. } in catalog.tools {
+            if !known.contains_key(&name) {
+                add_cli_tool(&name, &repo, &description)?;
+            }
+        }
+    }
+
+    if installed == 0 {
+        return Err(anyhow!("The bundle contains no tools for this platform ({})", host));
+    }
+    info(format!("Make sure {} is in your PATH", install_dir.display()));
+
+    Ok(())
+}
+
+fn append_file<W: std::io::Write>(builder: &mut tar::Builder<W>, path: &str, data: &[u8]) -> Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder.append_data(&mut header, path, data)?;
+    Ok(())
+}
+
+/// Read all files of a bundle into memory, by path
+fn read_bundle(bundle: &Path) -> Result<HashMap<String, Vec<u8>>> {
+    let file = File::open(bundle).with_context(|| format!("Failed to open {}", bundle.display()))?;
+    let mut archive = tar::Archive::new(GzDecoder::new(file));
+    let mut entries = HashMap::new();
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.to_string_lossy().to_string();
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data)?;
+        entries.insert(path, data);
+    }
+
+    Ok(entries)
+}
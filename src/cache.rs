@@ -0,0 +1,80 @@
+use anyhow::{anyhow, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::checksum::sha256_hex;
+use crate::github::Release;
+
+/// Root of the caches, ~/.cache/coolclis on Linux
+pub fn get_cache_dir() -> Result<PathBuf> {
+    let cache_dir = dirs::cache_dir().ok_or_else(|| anyhow!("Failed to determine cache directory"))?;
+    Ok(cache_dir.join("coolclis"))
+}
+
+/// Path of the cached metadata of a release, `latest` when no tag is given
+fn release_cache_path(repo: &str, tag: Option<&str>) -> Result<PathBuf> {
+    let name = match tag {
+        Some(tag) => format!("tag-{}.json", sanitize(tag)),
+        None => "latest.json".to_string(),
+    };
+    let mut path = get_cache_dir()?.join("releases");
+    for part in repo.split('/') {
+        path.push(sanitize(part));
+    }
+    Ok(path.join(name))
+}
+
+/// Path of the cached download of a URL
+fn download_cache_path(url: &str) -> Result<PathBuf> {
+    let file_name = url.rsplit('/').next().unwrap_or("download");
+    Ok(get_cache_dir()?
+        .join("downloads")
+        .join(&sha256_hex(url.as_bytes())[..16])
+        .join(sanitize(file_name)))
+}
+
+/// Get a cached release, ignoring entries older than `max_age` if given
+pub fn read_cached_release(repo: &str, tag: Option<&str>, max_age: Option<Duration>) -> Option<Release> {
+    let path = release_cache_path(repo, tag).ok()?;
+    if let Some(max_age) = max_age {
+        let modified = fs::metadata(&path).and_then(|m| m.modified()).ok()?;
+        if SystemTime::now().duration_since(modified).unwrap_or_default() > max_age {
+            return None;
+        }
+    }
+    serde_json::from_slice(&fs::read(path).ok()?).ok()
+}
+
+pub fn write_cached_release(repo: &str, tag: Option<&str>, release: &Release) -> Result<()> {
+    write_atomically(&release_cache_path(repo, tag)?, &serde_json::to_vec_pretty(release)?)
+}
+
+pub fn read_cached_download(url: &str) -> Option<Vec<u8>> {
+    fs::read(download_cache_path(url).ok()?).ok()
+}
+
+pub fn write_cached_download(url: &str, data: &[u8]) -> Result<()> {
+    write_atomically(&download_cache_path(url)?, data)
+}
+
+/// Write to a temporary file first, so readers never see a partially written entry
+fn write_atomically(path: &Path, data: &[u8]) -> Result<()> {
+    let parent = path.parent().ok_or_else(|| anyhow!("Invalid cache path {}", path.display()))?;
+    fs::create_dir_all(parent)?;
+    let temp_path = parent.join(format!(".{}.{}.tmp", std::process::id(), fastrand::u64(..)));
+    fs::write(&temp_path, data)?;
+    fs::rename(&temp_path, path).inspect_err(|_| {
+        let _ = fs::remove_file(&temp_path);
+    })?;
+    Ok(())
+}
+
+/// Make a string usable as a single path component
+pub fn sanitize(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_' | '+') { c } else { '_' })
+        .collect::<String>()
+        .trim_start_matches('.')
+        .to_string()
+}
@@ -0,0 +1,68 @@
+use sha2::{Digest, Sha256};
+
+/// SHA-256 digest of `data` as a lowercase hex string
+pub fn sha256_hex(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+/// Find the SHA-256 of `file_name` in the content of a checksum file, either a list of
+/// `<hash>  <file name>` lines as written by `sha256sum`, or a single hash
+pub fn find_sha256(checksums: &str, file_name: &str) -> Option<String> {
+    let is_sha256 = |hash: &str| hash.len() == 64 && hash.chars().all(|c| c.is_ascii_hexdigit());
+
+    let mut lines = checksums.lines().map(str::trim).filter(|line| !line.is_empty());
+    for line in lines.clone() {
+        let mut parts = line.split_whitespace();
+        let (Some(hash), Some(name)) = (parts.next(), parts.next()) else {
+            continue;
+        };
+        // `*` marks files hashed in binary mode, paths may have a leading `./`
+        let name = name.trim_start_matches('*').trim_start_matches("./");
+        if name == file_name && is_sha256(hash) {
+            return Some(hash.to_lowercase());
+        }
+    }
+
+    // A file with nothing but a hash, like `<asset>.sha256`
+    match (lines.next(), lines.next()) {
+        (Some(hash), None) if is_sha256(hash) => Some(hash.to_lowercase()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const A: &str = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08";
+    const B: &str = "60303ae22b998861bce3b28f33eec1be758a213c86c93c076dbe9f558c11c752";
+
+    #[test]
+    fn find_sha256_cases() {
+        let sums = format!("{A}  tool-linux-amd64.tar.gz\n{B}  tool-darwin-arm64.tar.gz\n");
+        let binary = format!("{A} *tool-linux-amd64\n{B} *./tool-darwin-arm64\n");
+        let upper = format!("{}  tool.zip\n", A.to_uppercase());
+        let cases: &[(&str, &str, Option<&str>)] = &[
+            // SHA256SUMS style lists
+            (&sums, "tool-linux-amd64.tar.gz", Some(A)),
+            (&sums, "tool-darwin-arm64.tar.gz", Some(B)),
+            (&sums, "tool-linux-amd64", None),
+            // Names of files hashed in binary mode
+            (&binary, "tool-linux-amd64", Some(A)),
+            (&binary, "tool-darwin-arm64", Some(B)),
+            // Bare `.sha256` files, with or without a trailing newline
+            (A, "anything.tar.gz", Some(A)),
+            (&format!("{B}\n"), "anything.tar.gz", Some(B)),
+            // Uppercase hex is returned in lowercase
+            (&upper, "tool.zip", Some(A)),
+            (&A.to_uppercase(), "tool.zip", Some(A)),
+            // Not a SHA-256 hash
+            (&format!("{}  tool.zip\n", &A[..40]), "tool.zip", None),
+            (&A[..63], "tool.zip", None),
+            ("", "tool.zip", None),
+        ];
+        for (checksums, file_name, expected) in cases {
+            assert_eq!(find_sha256(checksums, file_name).as_deref(), *expected, "{:?} in {:?}", file_name, checksums);
+        }
+    }
+}
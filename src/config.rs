@@ -3,14 +3,21 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
-use reqwest::StatusCode;
 use futures::stream::{FuturesUnordered, StreamExt};
 
+use crate::github::{get_latest_release, get_latest_releases, github_token, Release};
+use crate::platform::{rank_candidate_assets, Platform};
+use crate::report::info;
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct CliTool {
     pub name: String,
     pub repo: String,
     pub description: String,
+    /// Arguments the installed binary is run with to check that it works, `--version` if not set,
+    /// an empty string to skip the check
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub probe: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -76,6 +83,7 @@ pub fn add_cli_tool(name: &str, repo: &str, description: &str) -> Result<()> {
         name: name.to_string(),
         repo: repo.to_string(),
         description: description.to_string(),
+        probe: None,
     });
 
     // Save the updated config
@@ -83,7 +91,7 @@ pub fn add_cli_tool(name: &str, repo: &str, description: &str) -> Result<()> {
     let json = serde_json::to_string_pretty(&config)?;
     fs::write(config_path, json)?;
 
-    println!("Added tool '{}' ({}) to the configuration", name, repo);
+    info(format!("Added tool '{}' ({}) to the configuration", name, repo));
 
     Ok(())
 }
@@ -100,6 +108,28 @@ pub fn load_cli_tools() -> Result<HashMap<String, String>> {
     Ok(tools_map)
 }
 
+/// Resolve a tool given either as a predefined tool name or as owner/repo
+pub fn resolve_tool(tool: &str) -> Result<CliTool> {
+    let config = load_config_file()?;
+
+    if tool.contains('/') {
+        // Use the catalog entry of the repo if there is one
+        if let Some(cli_tool) = config.tools.into_iter().find(|t| t.repo == tool) {
+            return Ok(cli_tool);
+        }
+        return Ok(CliTool {
+            name: tool.split('/').next_back().unwrap().to_string(),
+            repo: tool.to_string(),
+            description: "No description provided".to_string(),
+            probe: None,
+        });
+    }
+
+    config.tools.into_iter()
+        .find(|t| t.name == tool)
+        .ok_or_else(|| anyhow!("Unknown tool: {}. Use the 'list' command to see available tools.", tool))
+}
+
 pub fn list_available_tools() -> Result<()> {
     let config = load_config_file()?;
 
@@ -114,46 +144,223 @@ pub fn list_available_tools() -> Result<()> {
     Ok(())
 }
 
-/// Checks if the GitHub repo for each tool is valid by sending a HEAD request to the releases/latest endpoint, in parallel.
+/// Checks if the GitHub repo for each tool is valid by fetching its latest release, in parallel.
+/// Requests go through the mirrors and are retried like any other.
 pub async fn check_cli_tools_links_streaming() -> Result<()> {
     let config = load_config_file()?;
-    let client = reqwest::Client::new();
+
+    // With a token, the whole catalog can be checked in a few GraphQL requests
+    if github_token().is_some() {
+        return check_cli_tools_links_batched(config).await;
+    }
+
     let mut futures = FuturesUnordered::new();
 
     for tool in config.tools {
-        let client = client.clone();
-        let name = tool.name.clone();
-        let repo = tool.repo.clone();
         futures.push(async move {
-            let url = format!("https://api.github.com/repos/{}/releases/latest", repo);
-            let res = client
-                .head(&url)
-                .header("User-Agent", "curl")
-                .send()
-                .await;
-            match res {
-                Ok(resp) => {
-                    if resp.status() == StatusCode::OK {
-                        (name, repo, true, None)
-                    } else {
-                        (name, repo, false, Some(format!("HTTP {}", resp.status())))
-                    }
-                }
-                Err(e) => {
-                    (name, repo, false, Some(e.to_string()))
-                }
-            }
+            // Requests are queued by the scheduler so we don't hammer the API
+            let release = get_latest_release(&tool.repo).await;
+            (tool.name, tool.repo, release)
         });
     }
 
     println!("{:<15} {:<30} STATUS", "NAME", "REPOSITORY");
     println!("{:<15} {:<30} ------", "----", "----------");
-    while let Some((name, repo, valid, err)) = futures.next().await {
-        if valid {
-            println!("{:<15} {:<30} OK", name, repo);
-        } else {
-            println!("{:<15} {:<30} INVALID: {}", name, repo, err.unwrap_or_else(|| "Unknown error".to_string()));
+    while let Some((name, repo, release)) = futures.next().await {
+        match release {
+            Ok(release) => println!("{:<15} {:<30} OK ({})", name, repo, release.tag_name),
+            Err(e) => println!("{:<15} {:<30} INVALID: {:#}", name, repo, e),
+        }
+    }
+    Ok(())
+}
+
+/// Checks all tools by fetching their latest releases in batches through the GitHub GraphQL API.
+async fn check_cli_tools_links_batched(config: CliToolsConfig) -> Result<()> {
+    let repos: Vec<String> = config.tools.iter().map(|tool| tool.repo.clone()).collect();
+    let releases = get_latest_releases(&repos).await;
+
+    println!("{:<15} {:<30} STATUS", "NAME", "REPOSITORY");
+    println!("{:<15} {:<30} ------", "----", "----------");
+    for tool in config.tools {
+        match releases.get(&tool.repo) {
+            Some(Ok(release)) => println!("{:<15} {:<30} OK ({})", tool.name, tool.repo, release.tag_name),
+            Some(Err(e)) => println!("{:<15} {:<30} INVALID: {}", tool.name, tool.repo, e),
+            None => println!("{:<15} {:<30} INVALID: Unknown error", tool.name, tool.repo),
         }
     }
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Targets of `check --platforms` when none are given
+pub const DEFAULT_CHECK_TARGETS: &[&str] = &["linux-x86_64", "linux-arm64", "darwin-x86_64", "darwin-arm64", "windows-x86_64"];
+
+/// Asset selection for one tool and target
+enum Coverage {
+    Selected(String),
+    Missing,
+    /// Several assets are equally good matches, the first one in the release would be installed
+    Ambiguous(Vec<String>),
+}
+
+/// Fetches the latest release of each tool and selects its asset for every target, printing
+/// a matrix of the results. Fails if any tool has no release, or no single asset for a target.
+pub async fn check_platforms(targets: &[Platform]) -> Result<()> {
+    let config = load_config_file()?;
+    let repos: Vec<String> = config.tools.iter().map(|tool| tool.repo.clone()).collect();
+    let releases = get_latest_releases(&repos).await;
+
+    let mut rows = Vec::new();
+    let mut details = Vec::new();
+    let mut problems = 0;
+    for tool in &config.tools {
+        let release = match releases.get(&tool.repo) {
+            Some(Ok(release)) => release,
+            Some(Err(e)) => {
+                details.push(format!("{}: no release: {}", tool.name, e));
+                problems += targets.len();
+                rows.push((tool.name.clone(), vec!["ERROR".to_string(); targets.len()]));
+                continue;
+            }
+            None => {
+                details.push(format!("{}: no release: Unknown error", tool.name));
+                problems += targets.len();
+                rows.push((tool.name.clone(), vec!["ERROR".to_string(); targets.len()]));
+                continue;
+            }
+        };
+
+        // Assets are selected with the name of the repository, like installs do
+        let name = tool.repo.split('/').next_back().unwrap_or(&tool.repo);
+        let mut cells = Vec::new();
+        for target in targets {
+            let cell = match coverage(release, name, target) {
+                Coverage::Selected(asset) => asset,
+                Coverage::Missing => {
+                    problems += 1;
+                    "MISSING".to_string()
+                }
+                Coverage::Ambiguous(assets) => {
+                    problems += 1;
+                    details.push(format!("{} on {}: {} match equally, {} would be installed",
+                        tool.name, target, assets.join(", "), assets[0]));
+                    "AMBIGUOUS".to_string()
+                }
+            };
+            cells.push(cell);
+        }
+        rows.push((tool.name.clone(), cells));
+    }
+
+    let headers: Vec<String> = targets.iter().map(Platform::to_string).collect();
+    let name_width = rows.iter().map(|(name, _)| name.len()).chain([4]).max().unwrap_or(4);
+    let widths: Vec<usize> = headers.iter().enumerate()
+        .map(|(i, header)| rows.iter().map(|(_, cells)| cells[i].len()).chain([header.len()]).max().unwrap_or(0))
+        .collect();
+    let print_row = |name: &str, cells: &[String]| {
+        let cells: Vec<String> = cells.iter().zip(&widths).map(|(cell, width)| format!("{:<width$}", cell, width = width)).collect();
+        println!("{:<name_width$}  {}", name, cells.join("  ").trim_end(), name_width = name_width);
+    };
+
+    print_row("TOOL", &headers);
+    print_row("----", &headers.iter().map(|h| "-".repeat(h.len())).collect::<Vec<_>>());
+    for (name, cells) in &rows {
+        print_row(name, cells);
+    }
+    if !details.is_empty() {
+        println!();
+        for detail in &details {
+            println!("{}", detail);
+        }
+    }
+
+    if problems > 0 {
+        return Err(anyhow!("{} of {} tool and target combinations have no single matching asset",
+            problems, config.tools.len() * targets.len()));
+    }
+    Ok(())
+}
+
+/// The asset an install of `release` for `target` would use
+fn coverage(release: &Release, tool_name: &str, target: &Platform) -> Coverage {
+    let candidates = rank_candidate_assets(release, tool_name, target);
+    let Some((best, first)) = candidates.first() else {
+        return Coverage::Missing;
+    };
+    let tied: Vec<String> = candidates.iter()
+        .filter(|(rank, _)| rank == best)
+        .map(|(_, asset)| asset.name.clone())
+        .collect();
+    if tied.len() > 1 {
+        Coverage::Ambiguous(tied)
+    } else {
+        Coverage::Selected(first.name.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::github::Asset;
+
+    fn release(assets: &[&str]) -> Release {
+        Release {
+            tag_name: "v1.0.0".to_string(),
+            assets: assets.iter().map(|name| Asset {
+                name: name.to_string(),
+                browser_download_url: format!("https://example.com/{}", name),
+                size: 1,
+            }).collect(),
+            prerelease: false,
+            draft: false,
+        }
+    }
+
+    fn cell(assets: &[&str], target: &str) -> String {
+        match coverage(&release(assets), "tool", &Platform::parse(target).unwrap()) {
+            Coverage::Selected(asset) => asset,
+            Coverage::Missing => "MISSING".to_string(),
+            Coverage::Ambiguous(assets) => format!("AMBIGUOUS {}", assets.join(" ")),
+        }
+    }
+
+    #[test]
+    fn coverage_cells() {
+        let assets = [
+            "tool-x86_64-unknown-linux-gnu.tar.gz",
+            "tool-aarch64-unknown-linux-musl.tar.gz",
+            "tool-x86_64-apple-darwin.zip",
+            "tool-x86_64-pc-windows-msvc.zip",
+            "tool-x86_64-unknown-linux-gnu.tar.gz.sha256",
+        ];
+        let cases = [
+            ("linux-x86_64-gnu", "tool-x86_64-unknown-linux-gnu.tar.gz"),
+            ("linux-arm64-musl", "tool-aarch64-unknown-linux-musl.tar.gz"),
+            ("darwin-x86_64", "tool-x86_64-apple-darwin.zip"),
+            ("windows-x86_64", "tool-x86_64-pc-windows-msvc.zip"),
+            ("darwin-arm64", "MISSING"),
+            // A gnu target never gets a musl build
+            ("linux-arm64-gnu", "MISSING"),
+        ];
+        for (target, expected) in cases {
+            assert_eq!(cell(&assets, target), expected, "{}", target);
+        }
+    }
+
+    #[test]
+    fn archives_are_preferred_over_bare_binaries() {
+        let assets = ["tool-darwin-arm64", "tool-darwin-arm64.tar.gz"];
+        assert_eq!(cell(&assets, "darwin-arm64"), "tool-darwin-arm64.tar.gz");
+        assert_eq!(cell(&["tool-darwin-arm64"], "darwin-arm64"), "tool-darwin-arm64");
+        assert_eq!(cell(&["tool-darwin-arm64.tar.gz", "tool-darwin-arm64.zip"], "darwin-arm64"), "tool-darwin-arm64.tar.gz");
+        // An archive that can't be unpacked is not a bare binary
+        assert_eq!(cell(&["tool-darwin-arm64.tar.xz", "tool-darwin-arm64.tar.gz"], "darwin-arm64"), "tool-darwin-arm64.tar.gz");
+        assert_eq!(cell(&["tool-darwin-arm64.tar.xz"], "darwin-arm64"), "MISSING");
+    }
+
+    #[test]
+    fn equally_good_matches_are_ambiguous() {
+        let assets = ["tool-linux-amd64.tar.gz", "tool-lite-linux-amd64.tar.gz", "tool-linux-amd64"];
+        assert_eq!(cell(&assets, "linux-x86_64-musl"), "AMBIGUOUS tool-linux-amd64.tar.gz tool-lite-linux-amd64.tar.gz");
+    }
+}
@@ -0,0 +1,227 @@
+use anyhow::{anyhow, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::cache::get_cache_dir;
+use crate::config::CliToolsConfig;
+use crate::github::github_token;
+use crate::install::get_install_dir;
+use crate::manifest::nearest_manifest;
+use crate::settings::{get_settings_path, load_settings};
+use crate::staging::STAGING_PREFIX;
+use crate::state::{get_state_dir, load_state};
+use crate::store::get_store_dir;
+
+/// Findings of the checks, printed as they are made
+#[derive(Default)]
+struct Diagnosis {
+    problems: usize,
+    warnings: usize,
+}
+
+impl Diagnosis {
+    fn ok(&mut self, message: impl AsRef<str>) {
+        println!("[ok]    {}", message.as_ref());
+    }
+
+    fn warn(&mut self, message: impl AsRef<str>, fix: impl AsRef<str>) {
+        self.warnings += 1;
+        println!("[warn]  {}", message.as_ref());
+        println!("        fix: {}", fix.as_ref());
+    }
+
+    fn problem(&mut self, message: impl AsRef<str>, fix: impl AsRef<str>) {
+        self.problems += 1;
+        println!("[error] {}", message.as_ref());
+        println!("        fix: {}", fix.as_ref());
+    }
+}
+
+/// Check the installation for common problems and print how to fix them.
+/// Fails if any problem was found, warnings alone don't.
+pub fn doctor(dir: Option<&PathBuf>) -> Result<()> {
+    let install_dir = get_install_dir(dir)?;
+    let mut diagnosis = Diagnosis::default();
+
+    check_path(&install_dir, &mut diagnosis);
+    check_bin_entries(&install_dir, &mut diagnosis)?;
+    check_leftovers(&install_dir, &mut diagnosis)?;
+    check_files(&mut diagnosis)?;
+    check_token(&mut diagnosis);
+
+    println!();
+    if diagnosis.problems > 0 {
+        return Err(anyhow!("Found {} problem(s) and {} warning(s)", diagnosis.problems, diagnosis.warnings));
+    }
+    println!("No problems found ({} warning(s))", diagnosis.warnings);
+    Ok(())
+}
+
+/// Directories of the PATH environment variable, in order
+fn path_dirs() -> Vec<PathBuf> {
+    std::env::var_os("PATH")
+        .map(|path| std::env::split_paths(&path).collect())
+        .unwrap_or_default()
+}
+
+fn same_dir(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// The install dir must be on PATH for the installed tools to be found
+fn check_path(install_dir: &Path, diagnosis: &mut Diagnosis) {
+    let dirs = path_dirs();
+    match dirs.iter().position(|d| same_dir(d, install_dir)) {
+        Some(position) => diagnosis.ok(format!("{} is on PATH (position {} of {})",
+            install_dir.display(), position + 1, dirs.len())),
+        None => diagnosis.problem(
+            format!("{} is not on PATH, installed tools can't be run by name", install_dir.display()),
+            format!("add `export PATH=\"{}:$PATH\"` to your shell profile (~/.bashrc, ~/.zshrc, ...)", install_dir.display()),
+        ),
+    }
+}
+
+/// Look for broken entries in the install dir, and for binaries with the same names that
+/// come first in PATH
+fn check_bin_entries(install_dir: &Path, diagnosis: &mut Diagnosis) -> Result<()> {
+    let Ok(entries) = fs::read_dir(install_dir) else {
+        diagnosis.problem(format!("{} can't be read", install_dir.display()),
+            format!("check that {} exists and is a directory you can read", install_dir.display()));
+        return Ok(());
+    };
+
+    let dirs = path_dirs();
+    let earlier_dirs: Vec<&PathBuf> = match dirs.iter().position(|d| same_dir(d, install_dir)) {
+        Some(position) => dirs[..position].iter().collect(),
+        None => Vec::new(),
+    };
+
+    let mut checked = 0;
+    let mut found = 0;
+    for entry in entries {
+        let path = entry?.path();
+        let name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+        if name.starts_with('.') || path.is_dir() {
+            continue;
+        }
+        checked += 1;
+
+        // A symlink whose target is gone
+        if path.is_symlink() && !path.exists() {
+            found += 1;
+            let target = fs::read_link(&path).map(|t| t.display().to_string()).unwrap_or_default();
+            diagnosis.problem(format!("{} is a dangling symlink to {}", path.display(), target),
+                format!("reinstall it with `coolclis install {}` or remove it with `rm {}`", name, path.display()));
+            continue;
+        }
+
+        if !is_executable(&path) {
+            found += 1;
+            diagnosis.problem(format!("{} is not executable", path.display()),
+                format!("run `chmod +x {}`", path.display()));
+        }
+
+        for dir in &earlier_dirs {
+            let other = dir.join(&name);
+            if other.is_file() && is_executable(&other) {
+                found += 1;
+                diagnosis.warn(format!("{} is shadowed by {}, which comes first in PATH", path.display(), other.display()),
+                    format!("remove {} or move {} before {} in PATH", other.display(), install_dir.display(), dir.display()));
+            }
+        }
+    }
+
+    if found == 0 {
+        diagnosis.ok(format!("{} entries in {} are executable and not shadowed", checked, install_dir.display()));
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(path).is_ok_and(|m| m.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+/// Temporary directories left behind by installs that were killed
+fn check_leftovers(install_dir: &Path, diagnosis: &mut Diagnosis) -> Result<()> {
+    let mut dirs = vec![install_dir.to_path_buf(), get_cache_dir()?.join("exec")];
+    for parent in [get_store_dir()?, get_cache_dir()?.join("exec")] {
+        if let Ok(entries) = fs::read_dir(parent) {
+            dirs.extend(entries.filter_map(|e| e.ok()).map(|e| e.path()).filter(|p| p.is_dir()));
+        }
+    }
+
+    let mut found = 0;
+    for dir in dirs {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.filter_map(|e| e.ok()) {
+            let name = entry.file_name().to_string_lossy().to_string();
+            // `<tool>_temp` directories were used by older versions
+            if name.starts_with(STAGING_PREFIX) || (name.ends_with("_temp") && entry.path().is_dir()) {
+                found += 1;
+                diagnosis.warn(format!("{} was left behind by an interrupted install", entry.path().display()),
+                    format!("remove it with `rm -rf {}`", entry.path().display()));
+            }
+        }
+    }
+
+    if found == 0 {
+        diagnosis.ok("No leftovers of interrupted installs");
+    }
+    Ok(())
+}
+
+/// The catalogs, settings, install state and project manifest must be readable
+fn check_files(diagnosis: &mut Diagnosis) -> Result<()> {
+    for catalog in [PathBuf::from("cli-tools.json"), get_state_dir()?.join("cli-tools.json")] {
+        if !catalog.exists() {
+            continue;
+        }
+        let result = fs::read_to_string(&catalog)
+            .map_err(anyhow::Error::from)
+            .and_then(|content| Ok(serde_json::from_str::<CliToolsConfig>(&content)?));
+        match result {
+            Ok(config) => diagnosis.ok(format!("Catalog {} has {} tools", catalog.display(), config.tools.len())),
+            Err(e) => diagnosis.problem(format!("Catalog {} can't be read: {}", catalog.display(), e),
+                format!("fix or remove {}, a default catalog is created when it's missing", catalog.display())),
+        }
+    }
+
+    match load_settings() {
+        Ok(_) => diagnosis.ok("Settings are valid"),
+        Err(e) => diagnosis.problem(e.to_string(),
+            format!("fix {} or remove it to use the defaults", get_settings_path()?.display())),
+    }
+
+    match load_state() {
+        Ok(state) => diagnosis.ok(format!("Install state lists {} tools", state.tools.len())),
+        Err(e) => diagnosis.problem(e.to_string(),
+            "remove it, then reinstall your tools to record them again"),
+    }
+
+    if let Err(e) = nearest_manifest() {
+        diagnosis.problem(e.to_string(), "fix the syntax of the manifest, see the README for its format");
+    }
+
+    Ok(())
+}
+
+fn check_token(diagnosis: &mut Diagnosis) {
+    if github_token().is_some() {
+        diagnosis.ok("A GitHub token is set");
+    } else {
+        diagnosis.warn("No GitHub token: API requests are limited to 60 per hour",
+            "set GITHUB_TOKEN or GH_TOKEN, e.g. `export GITHUB_TOKEN=$(gh auth token)`");
+    }
+}
@@ -1,136 +1,238 @@
-use anyhow::{anyhow, Context, Result};
-use indicatif::{ProgressBar, ProgressStyle};
-use reqwest::{header::{HeaderMap, HeaderValue, USER_AGENT}, StatusCode};
-use std::time::Duration;
+use anyhow::{anyhow, Result};
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, USER_AGENT};
+use std::future::Future;
+use std::path::PathBuf;
+
+use crate::mirrors::candidate_urls;
+use crate::report::{report, warning, Event};
+use crate::retry::{AttemptRecord, Failure, RequestError, RetryPolicy};
+use crate::scheduler::scheduler;
+
+/// A downloaded file, with the URL that actually served it
+pub struct Download {
+    pub data: Vec<u8>,
+    pub source_url: String,
+}
 
 pub struct Downloader {
     client: reqwest::Client,
-    max_attempts: usize,
-    retry_delay_secs: u64,
+    policy: RetryPolicy,
+    token: Option<String>,
 }
 
 impl Default for Downloader {
     fn default() -> Self {
-        Self::new(3, 120, 2)
+        Self::new(RetryPolicy::default())
     }
 }
 
 impl Downloader {
-    pub fn new(max_attempts: usize, timeout_secs: u64, retry_delay_secs: u64) -> Self {
-        let client = reqwest::Client::builder()
-            .timeout(Duration::from_secs(timeout_secs))
-            .build()
-            .expect("Failed to build HTTP client");
-
+    pub fn new(policy: RetryPolicy) -> Self {
+        // All downloaders share the scheduler's client, so connections are reused
         Self {
-            client,
-            max_attempts,
-            retry_delay_secs,
+            client: scheduler().client().clone(),
+            policy,
+            token: None,
         }
     }
 
-    pub async fn get_json<T: serde::de::DeserializeOwned>(&self, url: &str) -> Result<T> {
-        let mut headers = HeaderMap::new();
-        headers.insert(USER_AGENT, HeaderValue::from_static("coolclis"));
+    /// Send the given token as a bearer `Authorization` header with JSON requests
+    pub fn with_token(mut self, token: Option<String>) -> Self {
+        self.token = token;
+        self
+    }
 
-        let mut attempts = 0;
+    pub async fn get_json<T: serde::de::DeserializeOwned>(&self, url: &str) -> Result<T> {
+        self.fetch_json(url, None).await
+    }
 
-        while attempts < self.max_attempts {
-            attempts += 1;
+    pub async fn post_json<T: serde::de::DeserializeOwned>(&self, url: &str, body: &serde_json::Value) -> Result<T> {
+        self.fetch_json(url, Some(body)).await
+    }
 
-            match self.client.get(url).headers(headers.clone()).send().await {
-                Ok(response) => {
-                    if response.status() == StatusCode::NOT_FOUND {
-                        return Err(anyhow!("{} not found (404)", url));
-                    }
-                    if response.status().is_success() {
-                        match response.json::<T>().await {
-                            Ok(data) => return Ok(data),
-                            Err(e) => {
-                                if attempts < self.max_attempts {
-                                    println!("Failed to parse JSON (attempt {}): {}", attempts, e);
-                                    tokio::time::sleep(Duration::from_secs(self.retry_delay_secs)).await;
-                                } else {
-                                    return Err(anyhow!("Failed to parse JSON: {}", e));
-                                }
-                            }
-                        }
-                    } else if attempts < self.max_attempts {
-                        println!("Failed to fetch URL (attempt {}): {}", attempts, response.status());
-                        tokio::time::sleep(Duration::from_secs(self.retry_delay_secs)).await;
-                    } else {
-                        return Err(anyhow!("Failed to fetch URL: {}", response.status()));
-                    }
-                },
-                Err(e) => {
-                    if attempts < self.max_attempts {
-                        println!("Failed to send request (attempt {}): {}", attempts, e);
-                        tokio::time::sleep(Duration::from_secs(self.retry_delay_secs)).await;
-                    } else {
-                        return Err(anyhow!("Failed to send request: {}", e));
-                    }
-                }
+    async fn fetch_json<T: serde::de::DeserializeOwned>(&self, url: &str, body: Option<&serde_json::Value>) -> Result<T> {
+        let host = &host_of(url);
+        let (data, _) = self.with_mirrors(url, |candidate| async move {
+            let mut headers = HeaderMap::new();
+            headers.insert(USER_AGENT, HeaderValue::from_static("coolclis"));
+            // Never leak the token to a mirror
+            if let (Some(token), true) = (&self.token, host_of(&candidate) == *host) {
+                headers.insert(AUTHORIZATION, HeaderValue::from_str(&format!("Bearer {}", token))?);
             }
-        }
 
-        Err(anyhow!("Failed to fetch URL after {} attempts", self.max_attempts))
+            self.with_retries(&candidate, || self.json_attempt(&candidate, body, &headers)).await
+        }).await?;
+
+        Ok(data)
     }
 
-    pub async fn download_file(&self, url: &str, size: u64) -> Result<Vec<u8>> {
-        let pb = ProgressBar::new(size);
-        pb.set_style(
-            ProgressStyle::default_bar()
-                .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta})")
-                .unwrap()
-                .progress_chars("#>-"),
-        );
+    async fn json_attempt<T: serde::de::DeserializeOwned>(
+        &self,
+        url: &str,
+        body: Option<&serde_json::Value>,
+        headers: &HeaderMap,
+    ) -> Result<T, Failure> {
+        if let Some(path) = file_url_path(url) {
+            let bytes = tokio::fs::read(path).await.map_err(Failure::File)?;
+            return serde_json::from_slice(&bytes).map_err(|e| Failure::Decode(e.to_string()));
+        }
 
-        let mut attempts = 0;
+        let request = match body {
+            Some(body) => self.client.post(url).json(body),
+            None => self.client.get(url),
+        };
 
-        while attempts < self.max_attempts {
-            attempts += 1;
+        let _permit = scheduler().acquire(url).await;
+        let response = request.headers(headers.clone()).send().await.map_err(Failure::from_transport_error)?;
+        scheduler().observe(&response);
 
-            match self.download_attempt(url, &pb).await {
-                Ok(buffer) => {
-                    pb.finish_with_message("Download complete");
-                    return Ok(buffer);
-                }
-                Err(e) => {
-                    if attempts < self.max_attempts {
-                        println!("Download attempt {} failed: {}", attempts, e);
-                        println!("Retrying in {} seconds...", self.retry_delay_secs);
-                        tokio::time::sleep(Duration::from_secs(self.retry_delay_secs)).await;
-                    } else {
-                        pb.finish_with_message("Download failed");
-                        return Err(anyhow!("Failed to download file after {} attempts: {}", self.max_attempts, e));
-                    }
-                }
-            }
+        if !response.status().is_success() {
+            return Err(Failure::from_response(&response));
         }
 
-        Err(anyhow!("Failed to download file"))
+        let bytes = response.bytes().await.map_err(Failure::from_body_error)?;
+        serde_json::from_slice(&bytes).map_err(|e| Failure::Decode(e.to_string()))
+    }
+
+    /// Download a file, trying the mirrors configured for its URL in order.
+    /// Progress is reported with `url` as the key, whichever mirror serves the file.
+    pub async fn download_file(&self, url: &str, size: u64) -> Result<Download> {
+        report(Event::DownloadStarted { url: url.to_string(), size });
+
+        let result = self.with_mirrors(url, |candidate| async move {
+            self.with_retries(&candidate, || self.download_attempt(url, &candidate)).await
+        }).await;
+
+        match result {
+            Ok((data, source_url)) => {
+                report(Event::DownloadFinished {
+                    url: url.to_string(),
+                    source_url: source_url.clone(),
+                    size: data.len() as u64,
+                });
+                Ok(Download { data, source_url })
+            }
+            Err(e) => {
+                report(Event::DownloadFailed { url: url.to_string(), error: e.to_string() });
+                Err(e)
+            }
+        }
     }
 
-    async fn download_attempt(&self, url: &str, pb: &ProgressBar) -> Result<Vec<u8>> {
+    async fn download_attempt(&self, key: &str, url: &str) -> Result<Vec<u8>, Failure> {
+        let progress = |downloaded: u64| report(Event::DownloadProgress { url: key.to_string(), downloaded });
+        progress(0);
+
+        if let Some(path) = file_url_path(url) {
+            let buffer = tokio::fs::read(path).await.map_err(Failure::File)?;
+            progress(buffer.len() as u64);
+            return Ok(buffer);
+        }
+
+        let _permit = scheduler().acquire(url).await;
         let mut response = self.client.get(url)
             .header(USER_AGENT, "coolclis")
             .send()
             .await
-            .context("Failed to send download request")?;
+            .map_err(Failure::from_transport_error)?;
+        scheduler().observe(&response);
 
         if !response.status().is_success() {
-            return Err(anyhow!("Failed to download: HTTP status {}", response.status()));
+            return Err(Failure::from_response(&response));
         }
 
         let mut buffer = Vec::new();
         let mut downloaded: u64 = 0;
 
-        while let Some(chunk) = response.chunk().await.context("Failed to download chunk")? {
+        // The client's read timeout fails the chunk read when the transfer stalls
+        while let Some(chunk) = response.chunk().await.map_err(Failure::from_body_error)? {
             downloaded += chunk.len() as u64;
-            pb.set_position(downloaded);
+            progress(downloaded);
             buffer.extend_from_slice(&chunk);
+            scheduler().throttle(chunk.len()).await;
         }
 
         Ok(buffer)
     }
-}
\ No newline at end of file
+
+    /// Run `fetch` on each URL to try for `url` until one succeeds, and return its
+    /// result with the URL that served it
+    async fn with_mirrors<T, F, Fut>(&self, url: &str, mut fetch: F) -> Result<(T, String)>
+    where
+        F: FnMut(String) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let candidates = candidate_urls(url);
+        let mut failures = Vec::new();
+
+        for (i, candidate) in candidates.iter().enumerate() {
+            match fetch(candidate.clone()).await {
+                Ok(value) => return Ok((value, candidate.clone())),
+                Err(e) if candidates.len() == 1 => return Err(e),
+                Err(e) => {
+                    if i + 1 < candidates.len() {
+                        warning(format!("Failed to fetch from {}, trying next source", candidate));
+                    }
+                    failures.push(e);
+                }
+            }
+        }
+
+        // Keep the last error as the source, so callers can still inspect it
+        let summary = failures.iter().map(|e| e.to_string()).collect::<Vec<_>>().join("; ");
+        match failures.pop() {
+            Some(last) => Err(last.context(format!("All sources failed for {}: {}", url, summary))),
+            None => Err(anyhow!("No source to fetch {} from", url)),
+        }
+    }
+
+    /// Run `attempt` until it succeeds, fails with a non-retryable error, or the
+    /// policy runs out of attempts. The error carries the history of all attempts.
+    async fn with_retries<T, F, Fut>(&self, url: &str, mut attempt: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, Failure>>,
+    {
+        let max_attempts = self.policy.max_attempts.max(1);
+        let mut attempts = Vec::new();
+
+        for n in 1..=max_attempts {
+            let failure = match attempt().await {
+                Ok(value) => return Ok(value),
+                Err(failure) => failure,
+            };
+
+            let delay = (failure.is_retryable() && n < max_attempts)
+                .then(|| self.policy.delay(n, failure.retry_after()));
+
+            if let Some(delay) = delay {
+                report(Event::Retry {
+                    url: url.to_string(),
+                    attempt: n,
+                    delay_secs: delay.as_secs_f64(),
+                    error: failure.to_string(),
+                });
+            }
+            attempts.push(AttemptRecord { failure, delay });
+
+            match delay {
+                Some(delay) => tokio::time::sleep(delay).await,
+                None => break,
+            }
+        }
+
+        Err(RequestError { url: url.to_string(), attempts }.into())
+    }
+}
+
+fn host_of(url: &str) -> Option<String> {
+    reqwest::Url::parse(url).ok().and_then(|u| u.host_str().map(str::to_string))
+}
+
+/// Local path of a `file://` URL, so catalogs and mirrors can point at a local directory
+fn file_url_path(url: &str) -> Option<PathBuf> {
+    if !url.starts_with("file://") {
+        return None;
+    }
+    reqwest::Url::parse(url).ok()?.to_file_path().ok()
+}
@@ -0,0 +1,118 @@
+use anyhow::{anyhow, Context, Result};
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::cache::{get_cache_dir, sanitize};
+use crate::config::resolve_tool;
+use crate::install::{download_asset, unpack_asset};
+use crate::platform::Platform;
+use crate::report::info;
+use crate::staging::StagingDir;
+use crate::version::{find_release, tag_matches, VersionRequirement};
+
+/// File in an extraction directory recording the path of the executable, relative to it
+const EXECUTABLE_MARKER: &str = ".coolclis-executable";
+
+/// Run a tool given as `tool`, `tool@version` or `tool@^1.4` without installing it: the release
+/// is extracted into `~/.cache/coolclis/exec/<tool>/<version>/` once and reused by later runs
+pub async fn exec_tool(spec: &str, args: &[OsString]) -> Result<()> {
+    let (tool, version) = match spec.split_once('@') {
+        Some((tool, version)) => (tool, Some(version)),
+        None => (spec, None),
+    };
+    let repo = resolve_tool(tool)?.repo;
+    let name = repo.split('/').next_back().unwrap();
+    let tool_cache_dir = get_cache_dir()?.join("exec").join(sanitize(name));
+
+    let requirement = match version {
+        Some(v) => VersionRequirement::parse(v)?,
+        None => VersionRequirement::Latest,
+    };
+
+    // An exact version is looked up in the cache without asking GitHub
+    let cached = match &requirement {
+        VersionRequirement::Exact(tag) => cached_release(&tool_cache_dir, name, tag),
+        _ => None,
+    };
+    let executable = match cached {
+        Some(executable) => executable,
+        None => {
+            let release = find_release(&repo, &requirement).await?;
+            let dir = tool_cache_dir.join(sanitize(&release.tag_name));
+            match cached_executable(&dir) {
+                Some(executable) => executable,
+                None => {
+                    info(format!("Downloading {} {}...", name, release.tag_name));
+                    let (asset_name, data) = download_asset(name, &release, &Platform::host(), true).await?;
+                    extract_to_cache(name, &asset_name, &data, &dir)?
+                }
+            }
+        }
+    };
+
+    run_executable(&executable, args)
+}
+
+/// The executable of the cached release tagged `wanted`, or of a tag for the same version,
+/// like `v1.18.0` for `1.18`
+fn cached_release(tool_cache_dir: &Path, tool: &str, wanted: &str) -> Option<PathBuf> {
+    if let Some(executable) = cached_executable(&tool_cache_dir.join(sanitize(wanted))) {
+        return Some(executable);
+    }
+    fs::read_dir(tool_cache_dir).ok()?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| tag_matches(&entry.file_name().to_string_lossy(), wanted, tool))
+        .find_map(|entry| cached_executable(&entry.path()))
+}
+
+/// The executable of a complete extraction in `dir`, if there is one
+fn cached_executable(dir: &Path) -> Option<PathBuf> {
+    let relative = fs::read_to_string(dir.join(EXECUTABLE_MARKER)).ok()?;
+    let executable = dir.join(relative.trim());
+    executable.is_file().then_some(executable)
+}
+
+/// Extract an asset into a staging directory and rename it to `dir` once complete
+fn extract_to_cache(tool: &str, asset_name: &str, data: &[u8], dir: &Path) -> Result<PathBuf> {
+    let parent = dir.parent().ok_or_else(|| anyhow!("Invalid cache directory {}", dir.display()))?;
+    let staging = StagingDir::new(parent, tool)?;
+    let content_dir = staging.path().join("content");
+    fs::create_dir(&content_dir)?;
+
+    let executable = unpack_asset(tool, asset_name, data, &content_dir)?;
+    let relative = executable.strip_prefix(&content_dir)?;
+    fs::write(content_dir.join(EXECUTABLE_MARKER), relative.to_string_lossy().as_bytes())?;
+
+    // Another run may have extracted the same version in the meantime
+    if fs::rename(&content_dir, dir).is_err() {
+        if let Some(executable) = cached_executable(dir) {
+            return Ok(executable);
+        }
+        let _ = fs::remove_dir_all(dir);
+        fs::rename(&content_dir, dir)
+            .with_context(|| format!("Failed to move the extraction to {}", dir.display()))?;
+    }
+
+    Ok(dir.join(relative))
+}
+
+/// Replace this process with `executable` on Unix, or run it and exit with its status elsewhere
+pub fn run_executable(executable: &Path, args: &[OsString]) -> Result<()> {
+    let mut command = Command::new(executable);
+    command.args(args);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        let e = command.exec();
+        Err(anyhow!("Failed to run {}: {}", executable.display(), e))
+    }
+    #[cfg(not(unix))]
+    {
+        let status = command.status()
+            .with_context(|| format!("Failed to run {}", executable.display()))?;
+        std::process::exit(status.code().unwrap_or(1));
+    }
+}
@@ -0,0 +1,327 @@
+use anyhow::{anyhow, Result};
+use futures::stream::{FuturesUnordered, StreamExt};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+use crate::downloader::Downloader;
+use crate::report::warning;
+use crate::retry::RequestError;
+
+const API_BASE: &str = "https://api.github.com";
+
+/// Number of repositories looked up in a single GraphQL query
+const GRAPHQL_CHUNK_SIZE: usize = 50;
+
+/// Number of releases requested per page when listing releases
+const RELEASES_PER_PAGE: usize = 100;
+
+/// Maximum number of pages listed, i.e. the 1000 most recent releases
+const MAX_RELEASE_PAGES: usize = 10;
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Release {
+    pub tag_name: String,
+    pub assets: Vec<Asset>,
+    #[serde(default)]
+    pub prerelease: bool,
+    /// Only listed with a token that can push to the repository
+    #[serde(default)]
+    pub draft: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Asset {
+    pub name: String,
+    pub browser_download_url: String,
+    pub size: u64,
+}
+
+/// Get the GitHub token from the environment (GITHUB_TOKEN or GH_TOKEN), if any
+pub fn github_token() -> Option<String> {
+    ["GITHUB_TOKEN", "GH_TOKEN"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|token| !token.trim().is_empty())
+}
+
+fn api_downloader() -> Downloader {
+    Downloader::default().with_token(github_token())
+}
+
+pub async fn get_latest_release(repo: &str) -> Result<Release> {
+    let url = format!("{}/repos/{}/releases/latest", API_BASE, repo);
+    api_downloader().get_json::<Release>(&url).await
+}
+
+pub async fn get_specific_release(repo: &str, version: &str) -> Result<Release> {
+    let url = format!("{}/repos/{}/releases/tags/{}", API_BASE, repo, version);
+    api_downloader().get_json::<Release>(&url).await.map_err(|e| {
+        let not_found = e.downcast_ref::<RequestError>()
+            .is_some_and(|e| e.status() == Some(StatusCode::NOT_FOUND));
+        if not_found {
+            e.context(format!("Release {} not found in {}", version, repo))
+        } else {
+            e
+        }
+    })
+}
+
+/// List the releases of a repository, most recent first, one page of `RELEASES_PER_PAGE`
+/// releases after the other
+pub async fn list_releases(repo: &str) -> Result<Vec<Release>> {
+    let mut releases = Vec::new();
+    for page in 1..=MAX_RELEASE_PAGES {
+        let batch = get_releases_page(repo, page).await?;
+        let is_last = batch.len() < RELEASES_PER_PAGE;
+        releases.extend(batch);
+        if is_last {
+            break;
+        }
+    }
+    Ok(releases)
+}
+
+/// Get a page of the releases of a repository, starting at 1
+pub async fn get_releases_page(repo: &str, page: usize) -> Result<Vec<Release>> {
+    let url = format!("{}/repos/{}/releases?per_page={}&page={}", API_BASE, repo, RELEASES_PER_PAGE, page);
+    api_downloader().get_json::<Vec<Release>>(&url).await
+}
+
+/// Get the latest release of many repositories at once.
+///
+/// With a token, the releases are fetched through the GraphQL API in chunks of
+/// `GRAPHQL_CHUNK_SIZE` repositories per request. Without one, GraphQL is not
+/// available and each repository is looked up through the REST API, as are the
+/// repositories of a failed chunk and the releases with more than 100 assets.
+pub async fn get_latest_releases(repos: &[String]) -> HashMap<String, Result<Release>> {
+    match github_token() {
+        Some(token) => get_latest_releases_graphql(repos, &token).await,
+        None => get_latest_releases_rest(repos).await,
+    }
+}
+
+async fn get_latest_releases_rest(repos: &[String]) -> HashMap<String, Result<Release>> {
+    let mut futures = FuturesUnordered::new();
+    for repo in repos {
+        futures.push(async move { (repo.clone(), get_latest_release(repo).await) });
+    }
+
+    let mut results = HashMap::new();
+    while let Some((repo, release)) = futures.next().await {
+        results.insert(repo, release);
+    }
+    results
+}
+
+async fn get_latest_releases_graphql(repos: &[String], token: &str) -> HashMap<String, Result<Release>> {
+    let downloader = Downloader::default().with_token(Some(token.to_string()));
+    let url = format!("{}/graphql", API_BASE);
+    let mut results = HashMap::new();
+
+    // Repositories to look up through the REST API instead
+    let mut rest_repos = Vec::new();
+    for chunk in repos.chunks(GRAPHQL_CHUNK_SIZE) {
+        let query = build_latest_releases_query(chunk);
+        let response = downloader.post_json::<Value>(&url, &json!({ "query": query })).await
+            .and_then(|response| match request_error(&response) {
+                Some(error) => Err(anyhow!("{}", error)),
+                None => Ok(response),
+            });
+        match response {
+            Ok(response) => {
+                for (index, repo) in chunk.iter().enumerate() {
+                    match parse_latest_release(&response, index) {
+                        // The rest of the assets would take another query per repository
+                        Ok(LatestRelease { has_more_assets: true, .. }) => rest_repos.push(repo.clone()),
+                        release => {
+                            results.insert(repo.clone(), release.map(|r| r.release));
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                // Mirrors and `coolclis serve` may only answer the REST API, and rate limits
+                // or missing scopes fail the whole query
+                warning(format!("GraphQL request failed, looking up {} repositories one by one: {}", chunk.len(), e));
+                rest_repos.extend(chunk.iter().cloned());
+            }
+        }
+    }
+
+    results.extend(get_latest_releases_rest(&rest_repos).await);
+    results
+}
+
+/// A latest release parsed from a GraphQL response
+struct LatestRelease {
+    release: Release,
+    /// The release has more assets than the query returned
+    has_more_assets: bool,
+}
+
+/// Build a query with one aliased `repository` field (`r0`, `r1`, ...) per repo
+fn build_latest_releases_query(repos: &[String]) -> String {
+    let mut query = String::from("query {");
+    for (index, repo) in repos.iter().enumerate() {
+        let (owner, name) = repo.split_once('/').unwrap_or((repo, ""));
+        // JSON string literals are valid GraphQL string literals
+        query.push_str(&format!(
+            " r{}: repository(owner: {}, name: {}) {{ latestRelease {{ tagName releaseAssets(first: 100) {{ nodes {{ name downloadUrl size }} pageInfo {{ hasNextPage }} }} }} }}",
+            index,
+            Value::from(owner),
+            Value::from(name)
+        ));
+    }
+    query.push_str(" }");
+    query
+}
+
+/// Error failing a whole GraphQL query, like a rate limit or a missing scope, rather than the
+/// lookup of one repository. GitHub answers those with HTTP 200, no data and errors without a path.
+fn request_error(response: &Value) -> Option<String> {
+    let errors = response["errors"].as_array().map(Vec::as_slice).unwrap_or_default();
+    let general = errors.iter().find(|e| e["path"].as_array().is_none_or(|path| path.is_empty()));
+    if general.is_none() && !response["data"].is_null() {
+        return None;
+    }
+    let message = general.or(errors.first())
+        .and_then(|e| e["message"].as_str())
+        .unwrap_or("the response has no data");
+    Some(message.to_string())
+}
+
+fn parse_latest_release(response: &Value, index: usize) -> Result<LatestRelease> {
+    let alias = format!("r{}", index);
+
+    let repository = &response["data"][&alias];
+    if repository.is_null() {
+        // Errors for a missing repository are reported with the alias as their path
+        let message = response["errors"]
+            .as_array()
+            .and_then(|errors| {
+                errors.iter().find(|e| e["path"].get(0).and_then(Value::as_str) == Some(alias.as_str()))
+            })
+            .and_then(|e| e["message"].as_str())
+            .unwrap_or("Repository not found");
+        return Err(anyhow!("{}", message));
+    }
+
+    let release = &repository["latestRelease"];
+    if release.is_null() {
+        return Err(anyhow!("No release found"));
+    }
+
+    let assets = release["releaseAssets"]["nodes"]
+        .as_array()
+        .map(|nodes| {
+            nodes
+                .iter()
+                .map(|node| Asset {
+                    name: node["name"].as_str().unwrap_or_default().to_string(),
+                    browser_download_url: node["downloadUrl"].as_str().unwrap_or_default().to_string(),
+                    size: node["size"].as_u64().unwrap_or(0),
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    // The latest release is never a prerelease or a draft
+    Ok(LatestRelease {
+        release: Release {
+            tag_name: release["tagName"].as_str().unwrap_or_default().to_string(),
+            assets,
+            prerelease: false,
+            draft: false,
+        },
+        has_more_assets: release["releaseAssets"]["pageInfo"]["hasNextPage"].as_bool() == Some(true),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn repos(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    #[test]
+    fn query_has_one_alias_per_repository() {
+        let query = build_latest_releases_query(&repos(&["BurntSushi/ripgrep", "sharkdp/fd"]));
+        assert!(query.starts_with("query { r0: repository(owner: \"BurntSushi\", name: \"ripgrep\") "));
+        assert!(query.contains(" r1: repository(owner: \"sharkdp\", name: \"fd\") "));
+        assert!(query.contains("pageInfo { hasNextPage }"));
+        assert!(query.ends_with(" }"));
+    }
+
+    #[test]
+    fn query_escapes_owner_and_name() {
+        let query = build_latest_releases_query(&repos(&["a\"b/c\\d", "noslash"]));
+        assert!(query.contains(r#"r0: repository(owner: "a\"b", name: "c\\d")"#));
+        assert!(query.contains(r#"r1: repository(owner: "noslash", name: "")"#));
+    }
+
+    #[test]
+    fn parse_release() {
+        let response = json!({ "data": { "r0": { "latestRelease": {
+            "tagName": "v1.2.3",
+            "releaseAssets": {
+                "nodes": [{ "name": "tool-linux.tar.gz", "downloadUrl": "https://example.com/tool-linux.tar.gz", "size": 42 }],
+                "pageInfo": { "hasNextPage": false },
+            },
+        } } } });
+        let latest = parse_latest_release(&response, 0).unwrap();
+        assert_eq!(latest.release.tag_name, "v1.2.3");
+        assert_eq!(latest.release.assets.len(), 1);
+        assert_eq!(latest.release.assets[0].name, "tool-linux.tar.gz");
+        assert_eq!(latest.release.assets[0].size, 42);
+        assert!(!latest.has_more_assets);
+        assert!(request_error(&response).is_none());
+    }
+
+    #[test]
+    fn parse_release_with_more_assets() {
+        let response = json!({ "data": { "r0": { "latestRelease": {
+            "tagName": "v1.0.0",
+            "releaseAssets": { "nodes": [], "pageInfo": { "hasNextPage": true } },
+        } } } });
+        assert!(parse_latest_release(&response, 0).unwrap().has_more_assets);
+    }
+
+    #[test]
+    fn parse_missing_repository() {
+        let response = json!({
+            "data": { "r0": null, "r1": { "latestRelease": null } },
+            "errors": [{
+                "type": "NOT_FOUND",
+                "path": ["r0"],
+                "message": "Could not resolve to a Repository with the name 'x/gone'.",
+            }],
+        });
+        // An error about one repository doesn't fail the others
+        assert!(request_error(&response).is_none());
+        let error = parse_latest_release(&response, 0).err().unwrap();
+        assert_eq!(error.to_string(), "Could not resolve to a Repository with the name 'x/gone'.");
+        assert_eq!(parse_latest_release(&response, 1).err().unwrap().to_string(), "No release found");
+    }
+
+    #[test]
+    fn errors_failing_the_whole_query() {
+        let rate_limited = json!({
+            "data": null,
+            "errors": [{ "type": "RATE_LIMITED", "message": "API rate limit exceeded" }],
+        });
+        assert_eq!(request_error(&rate_limited).as_deref(), Some("API rate limit exceeded"));
+
+        let no_path = json!({
+            "data": { "r0": null },
+            "errors": [{ "type": "INSUFFICIENT_SCOPES", "message": "Your token has not been granted the required scopes" }],
+        });
+        assert_eq!(request_error(&no_path).as_deref(), Some("Your token has not been granted the required scopes"));
+
+        assert!(request_error(&json!({ "data": null })).is_some());
+        assert!(request_error(&json!({})).is_some());
+    }
+}
@@ -0,0 +1,419 @@
+use anyhow::{anyhow, Context, Result};
+use futures::stream::{self, StreamExt};
+use std::fs::{self, File};
+use std::io::{self, Cursor};
+use std::path::{Path, PathBuf};
+
+use crate::checksum::sha256_hex;
+use crate::config::resolve_tool;
+use crate::downloader::Downloader;
+use crate::github::{get_latest_release, Release};
+use crate::manifest::{nearest_manifest, MANIFEST_FILE};
+use crate::multiarch::{configured_targets, install_multiarch_tool};
+use crate::platform::{find_candidate_assets, preferred_libc, Platform};
+use crate::preflight::preflight_asset;
+use crate::report::{info, report, warning, Event};
+use crate::shim::write_shim;
+use crate::smoke::smoke_test;
+use crate::staging::StagingDir;
+use crate::state::{load_state, SourceAsset, StoredVersion};
+use crate::store::{add_to_store, adopt_existing, prune_versions, record_install, record_project_version, stored_executable, stored_pin, tool_store_dir, version_dir};
+use crate::unpack::extract_archive;
+use crate::version::{find_release, VersionRequirement};
+
+/// Get the installation directory, defaulting to ~/.local/bin
+pub fn get_install_dir(dir: Option<&PathBuf>) -> Result<PathBuf> {
+    match dir {
+        Some(d) => Ok(d.clone()),
+        None => {
+            let mut home_dir = dirs::home_dir().ok_or_else(|| anyhow!("Could not determine home directory"))?;
+            home_dir.push(".local");
+            home_dir.push("bin");
+            fs::create_dir_all(&home_dir)?;
+            Ok(home_dir)
+        }
+    }
+}
+
+/// A tool that was installed
+#[derive(Debug)]
+pub struct InstalledTool {
+    pub version: String,
+    pub path: PathBuf,
+}
+
+/// Install tools given as predefined tool names or owner/repo, up to `jobs` at a time.
+/// Failures don't stop the other installs; they are listed in a summary at the end.
+/// Without tools, installs the tools pinned by the nearest project manifest.
+/// With a `target`, the binaries for it are only copied into the directory, even if they could
+/// run here. Without one, the `targets` setting installs the binaries for several platforms.
+pub async fn install_tools(tools: &[String], version: Option<&str>, dir: Option<&PathBuf>, jobs: usize, target: Option<&Platform>) -> Result<()> {
+    if version.is_some() && tools.len() != 1 {
+        return Err(anyhow!("--version can only be used when installing a single tool"));
+    }
+
+    let install_dir = get_install_dir(dir)?;
+
+    if tools.is_empty() {
+        if target.is_some() {
+            return Err(anyhow!("--target can't be used with the tools of a project manifest"));
+        }
+        return install_manifest_tools(&install_dir, jobs).await;
+    }
+
+    // Without a target, the `targets` setting installs every tool behind a dispatcher
+    if target.is_none() {
+        let targets = configured_targets()?;
+        if !targets.is_empty() {
+            return install_multiarch_tools(tools, version, &install_dir, jobs, &targets).await;
+        }
+    }
+
+    if let (Some(target), None) = (target, dir) {
+        return Err(anyhow!("Binaries for --target {} are installed as plain files, give the directory to install them to with --dir", target));
+    }
+
+    // A single tool is installed as is, without a summary
+    if let [tool] = tools {
+        let repo = resolve_tool(tool)?.repo;
+        install_tool(&repo, version, Some(&install_dir), target).await?;
+        if target.is_none() {
+            info(format!("Make sure {} is in your PATH", install_dir.display()));
+        }
+        return Ok(());
+    }
+
+    let install_dir = &install_dir;
+    let results = stream::iter(tools)
+        .map(|tool| async move {
+            let result = match resolve_tool(tool) {
+                Ok(cli_tool) => install_tool(&cli_tool.repo, None, Some(install_dir), target).await,
+                Err(e) => Err(e),
+            };
+            (tool.clone(), result)
+        })
+        .buffer_unordered(jobs.max(1))
+        .collect()
+        .await;
+
+    summarize(tools, results, install_dir, target.is_none())
+}
+
+/// Install tools for each of the `targets` setting
+async fn install_multiarch_tools(tools: &[String], version: Option<&str>, install_dir: &Path, jobs: usize, targets: &[Platform]) -> Result<()> {
+    let names: Vec<String> = targets.iter().map(Platform::to_string).collect();
+    info(format!("Installing for {}", names.join(", ")));
+
+    let results = stream::iter(tools)
+        .map(|tool| async move {
+            let result = match resolve_tool(tool) {
+                Ok(cli_tool) => install_multiarch_tool(&cli_tool.repo, version, install_dir, targets).await,
+                Err(e) => Err(e),
+            };
+            (tool.clone(), result)
+        })
+        .buffer_unordered(jobs.max(1))
+        .collect()
+        .await;
+
+    summarize(tools, results, install_dir, true)
+}
+
+/// Install the tools pinned by the nearest manifest, behind shims
+async fn install_manifest_tools(install_dir: &Path, jobs: usize) -> Result<()> {
+    let (path, manifest) = nearest_manifest()?
+        .ok_or_else(|| anyhow!("No tools given and no {} found in this directory or its parents", MANIFEST_FILE))?;
+    info(format!("Installing the tools of {}", path.display()));
+
+    // Results are labeled with the names used in the manifest
+    let names: Vec<String> = manifest.tools.keys().cloned().collect();
+    let results = stream::iter(names.iter().cloned().zip(manifest.pinned_tools()?))
+        .map(|(name, pinned)| async move {
+            (name, install_project_tool(&pinned.repo, &pinned.version, install_dir).await)
+        })
+        .buffer_unordered(jobs.max(1))
+        .collect()
+        .await;
+
+    summarize(&names, results, install_dir, true)
+}
+
+/// Print a summary of the installs in the order the tools were given, failing if any failed
+fn summarize(tools: &[String], mut results: Vec<(String, Result<InstalledTool>)>, install_dir: &Path, path_hint: bool) -> Result<()> {
+    for (tool, result) in &results {
+        if let Err(e) = result {
+            report(Event::Failed { tool: tool.clone(), error: format!("{:#}", e) });
+        }
+    }
+
+    results.sort_by_key(|(tool, _)| tools.iter().position(|t| t == tool));
+    info("");
+    info(format!("{:<15} {:<15} RESULT", "TOOL", "VERSION"));
+    info(format!("{:<15} {:<15} ------", "----", "-------"));
+    for (tool, result) in &results {
+        match result {
+            Ok(installed) => info(format!("{:<15} {:<15} installed to {}", tool, installed.version, installed.path.display())),
+            Err(e) => info(format!("{:<15} {:<15} FAILED: {:#}", tool, "-", e)),
+        }
+    }
+
+    let failed = results.iter().filter(|(_, result)| result.is_err()).count();
+    if path_hint && failed < results.len() {
+        info(format!("Make sure {} is in your PATH", install_dir.display()));
+    }
+    if failed > 0 {
+        return Err(anyhow!("{} of {} tools failed to install", failed, results.len()));
+    }
+
+    Ok(())
+}
+
+/// Print the latest release of a tool, and the versions of it in the store
+pub async fn list_available_versions(tool: &str) -> Result<()> {
+    let cli_tool = resolve_tool(tool)?;
+    let release = get_latest_release(&cli_tool.repo).await?;
+    let name = cli_tool.repo.split('/').next_back().unwrap();
+    let installed: Vec<String> = load_state()?.tools.get(name)
+        .map(|t| t.versions.keys().cloned().collect())
+        .unwrap_or_default();
+
+    println!("Latest release of {}: {}", cli_tool.repo, release.tag_name);
+    if !installed.is_empty() {
+        println!("Installed: {}", installed.join(", "));
+    }
+    Ok(())
+}
+
+/// Install a release of `repo` for this machine, or for a `target` platform. Binaries for a
+/// target are copied into the directory as they are, without going through the store or being run.
+pub async fn install_tool(repo: &str, version: Option<&str>, dir: Option<&PathBuf>, target: Option<&Platform>) -> Result<InstalledTool> {
+    let tool = repo.split('/').next_back().unwrap();
+    let (tag, asset_name, data) = download_release(tool, repo, version, target).await?;
+
+    let install_dir = get_install_dir(dir)?;
+    let for_target = target.is_some();
+    let file_path = {
+        let (tool, repo, tag) = (tool.to_string(), repo.to_string(), tag.clone());
+        run_blocking(move || if for_target {
+            install_foreign_binary(&tool, &asset_name, &data, &install_dir)
+        } else {
+            install_binary(&tool, &repo, &tag, &asset_name, &data, &install_dir)
+        }).await?
+    };
+
+    report(Event::Installed { tool: tool.to_string(), path: file_path.clone() });
+
+    Ok(InstalledTool {
+        version: tag,
+        path: file_path,
+    })
+}
+
+/// Install a version of `repo` pinned by a project manifest, and a shim for it in `install_dir`.
+/// The active version outside of projects doesn't change.
+pub async fn install_project_tool(repo: &str, version: &str, install_dir: &Path) -> Result<InstalledTool> {
+    let tool = repo.split('/').next_back().unwrap();
+    let link = install_dir.join(tool);
+    adopt_existing(tool, repo, &link)?;
+
+    let (tag, _) = store_project_version(tool, repo, version, &link).await?;
+    write_shim(tool, &link)?;
+
+    report(Event::Installed { tool: tool.to_string(), path: link.clone() });
+
+    Ok(InstalledTool {
+        version: tag,
+        path: link,
+    })
+}
+
+/// Make sure a version of a tool pinned by a project is in the store, downloading it if needed.
+/// Versions are stored under the tag of their release, so `1.18` is stored as `v1.18.0`.
+/// Returns the tag and the path of the executable.
+pub async fn store_project_version(tool: &str, repo: &str, version: &str, link: &Path) -> Result<(String, PathBuf)> {
+    let (tag, stored) = match stored_pin(tool, version)? {
+        Some(tag) => (tag, None),
+        None => {
+            let (tag, asset_name, data) = download_release(tool, repo, Some(version), None).await?;
+            let (tool, stored_tag) = (tool.to_string(), tag.clone());
+            let stored = run_blocking(move || unpack_to_store(&tool, &stored_tag, &asset_name, &data)).await?;
+            (tag, Some(stored))
+        }
+    };
+    record_project_version(tool, repo, &tag, stored, link)?;
+
+    let executable = stored_executable(tool, &tag)?
+        .ok_or_else(|| anyhow!("{} {} is missing from the store", tool, tag))?;
+    Ok((tag, executable))
+}
+
+/// Find and download the asset of a release of `repo` for this machine, or for a `target` platform.
+/// Returns the tag of the release, the name of the asset and its content.
+pub async fn download_release(tool: &str, repo: &str, version: Option<&str>, target: Option<&Platform>) -> Result<(String, String, Vec<u8>)> {
+    let release = resolve_release(tool, repo, version).await?;
+    let (asset_name, data) = match target {
+        Some(target) => download_asset(tool, &release, target, false).await?,
+        None => download_asset(tool, &release, &Platform::host(), true).await?,
+    };
+    Ok((release.tag_name, asset_name, data))
+}
+
+/// Get the release of `repo` to install, the latest one unless a version or a requirement
+/// like `^1.4` is given
+pub async fn resolve_release(tool: &str, repo: &str, version: Option<&str>) -> Result<Release> {
+    report(Event::Resolving { tool: tool.to_string(), repo: repo.to_string() });
+
+    let requirement = match version {
+        Some(v) => VersionRequirement::parse(v)?,
+        None => VersionRequirement::Latest,
+    };
+    let release = find_release(repo, &requirement).await?;
+
+    report(Event::Resolved { tool: tool.to_string(), version: release.tag_name.clone() });
+    Ok(release)
+}
+
+/// Find and download the asset of `release` for `platform`. With `preflight`, for binaries meant
+/// to run on this machine, assets whose executable can't run on this system are skipped for the
+/// next best match. Returns the name of the asset and its content.
+pub async fn download_asset(tool: &str, release: &Release, platform: &Platform, preflight: bool) -> Result<(String, Vec<u8>)> {
+    let candidates = find_candidate_assets(release, tool, platform);
+    if candidates.is_empty() {
+        return Err(anyhow!("No suitable asset found for {}", platform));
+    }
+
+    let has_build = |build: &str| candidates.iter().any(|a| a.name.to_lowercase().contains(build));
+    if has_build("gnu") && has_build("musl") {
+        let (build, reason) = preferred_libc(platform);
+        info(format!("Preferring the {} build of {}: {}", build, tool, reason));
+    }
+
+    // Executables for another platform can't be checked here
+    if !preflight {
+        let asset = candidates[0];
+        report(Event::AssetSelected { tool: tool.to_string(), asset: asset.name.clone(), size: asset.size });
+        let download = Downloader::default().download_file(&asset.browser_download_url, asset.size).await?;
+        return Ok((asset.name.clone(), download.data));
+    }
+
+    let downloader = Downloader::default();
+    let mut rejected = Vec::new();
+    for asset in candidates {
+        report(Event::AssetSelected { tool: tool.to_string(), asset: asset.name.clone(), size: asset.size });
+        let download = downloader.download_file(&asset.browser_download_url, asset.size).await?;
+
+        let (tool_name, asset_name, data) = (tool.to_string(), asset.name.clone(), download.data);
+        let (data, checked) = run_blocking(move || {
+            let checked = preflight_asset(&tool_name, &asset_name, &data);
+            Ok((data, checked))
+        }).await?;
+        match checked {
+            Ok(()) => return Ok((asset.name.clone(), data)),
+            Err(e) => {
+                warning(format!("Skipping {}, it can't run on this system: {:#}", asset.name, e));
+                rejected.push(asset.name.clone());
+            }
+        }
+    }
+
+    Err(anyhow!("None of the assets of {} {} for {} can run on this system (tried {})",
+        tool, release.tag_name, platform, rejected.join(", ")))
+}
+
+/// Run blocking work, like unpacking, hashing or running an executable, on a thread of its own
+/// so that the other installs running concurrently keep making progress
+pub async fn run_blocking<T, F>(work: F) -> Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T> + Send + 'static,
+{
+    match tokio::task::spawn_blocking(work).await {
+        Ok(result) => result,
+        Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+        Err(e) => Err(anyhow!("Install task failed: {}", e)),
+    }
+}
+
+/// Install a downloaded asset, either an archive containing the executable or the executable itself.
+///
+/// The asset is unpacked into the store as `store/<tool>/<version>/`, then the bin entry in
+/// `install_dir` is atomically replaced with a symlink to its executable, which is then run to
+/// check that it works. Other versions stay in the store, for `use` and `rollback`.
+pub fn install_binary(tool: &str, repo: &str, version: &str, asset_name: &str, data: &[u8], install_dir: &Path) -> Result<PathBuf> {
+    let stored = unpack_to_store(tool, version, asset_name, data)?;
+
+    let link = install_dir.join(tool);
+    adopt_existing(tool, repo, &link)?;
+    // The bin entry may be a shim, the stored executable is what was installed
+    let executable = version_dir(tool, version)?.join(&stored.executable);
+    record_install(tool, repo, version, stored, &link)?;
+    smoke_test(tool, repo, version, &executable)?;
+    prune_versions(tool)?;
+
+    Ok(link)
+}
+
+/// Install the executable of an asset for another platform as a plain file in `install_dir`,
+/// e.g. the root filesystem of a container image. It's not added to the store, which is for
+/// the tools of this machine, and not run.
+fn install_foreign_binary(tool: &str, asset_name: &str, data: &[u8], install_dir: &Path) -> Result<PathBuf> {
+    fs::create_dir_all(install_dir)?;
+    let staging = StagingDir::new(install_dir, tool)?;
+    let executable = unpack_asset(tool, asset_name, data, staging.path())?;
+
+    // Keep the extension of Windows executables
+    let file_name = match executable.extension() {
+        Some(ext) if ext.eq_ignore_ascii_case("exe") => format!("{}.exe", tool),
+        _ => tool.to_string(),
+    };
+    let dest = install_dir.join(file_name);
+    fs::rename(&executable, &dest)
+        .with_context(|| format!("Failed to move the executable to {}", dest.display()))?;
+
+    Ok(dest)
+}
+
+/// Unpack a downloaded asset into a staging directory in the store, then rename it into place.
+/// An interrupted or failed unpack leaves the store untouched.
+fn unpack_to_store(tool: &str, version: &str, asset_name: &str, data: &[u8]) -> Result<StoredVersion> {
+    let staging = StagingDir::new(&tool_store_dir(tool)?, tool)?;
+    let content_dir = staging.path().join("content");
+    fs::create_dir(&content_dir)?;
+
+    let executable = unpack_asset(tool, asset_name, data, &content_dir)?;
+    let relative = executable.strip_prefix(&content_dir)?.to_path_buf();
+    let mut stored = add_to_store(tool, version, &content_dir, &relative)?;
+    stored.asset = Some(SourceAsset { name: asset_name.to_string(), sha256: sha256_hex(data) });
+    Ok(stored)
+}
+
+/// Unpack a downloaded asset into `dest_dir`, either an archive containing the executable or
+/// the executable itself, and return the path of the executable
+pub fn unpack_asset(tool: &str, asset_name: &str, data: &[u8], dest_dir: &Path) -> Result<PathBuf> {
+    // Check if the downloaded file is an archive that needs extraction
+    let executable = if asset_name.ends_with(".tar.gz") || asset_name.ends_with(".tgz") || asset_name.ends_with(".zip") {
+        report(Event::Extracting { tool: tool.to_string(), asset: asset_name.to_string() });
+
+        let extracted_path = extract_archive(data, asset_name, dest_dir, tool)?
+            .ok_or_else(|| anyhow!("Could not find executable in extracted archive"))?;
+
+        report(Event::ExecutableFound { tool: tool.to_string(), path: extracted_path.clone() });
+        extracted_path
+    } else {
+        // It's a direct binary
+        let file_path = dest_dir.join(tool);
+        let mut file = File::create(&file_path)?;
+        io::copy(&mut Cursor::new(data), &mut file)?;
+        file.sync_all()?;
+        file_path
+    };
+
+    // Make the file executable on Unix systems
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&executable, fs::Permissions::from_mode(0o755))?;
+    }
+
+    Ok(executable)
+}
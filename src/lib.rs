@@ -1,3 +1,28 @@
+pub mod bundle;
+pub mod cache;
+pub mod checksum;
 pub mod downloader;
+pub mod exec;
 pub mod config;
-pub mod unpack;
\ No newline at end of file
+pub mod doctor;
+pub mod github;
+pub mod install;
+pub mod manifest;
+pub mod mirrors;
+pub mod multiarch;
+pub mod platform;
+pub mod preflight;
+pub mod report;
+pub mod retry;
+pub mod rollback;
+pub mod scheduler;
+pub mod serve;
+pub mod settings;
+pub mod shim;
+pub mod smoke;
+pub mod staging;
+pub mod state;
+pub mod store;
+pub mod unpack;
+pub mod verify;
+pub mod version;
@@ -1,41 +1,115 @@
 use anyhow::{anyhow, Result};
-use clap::{Parser, Subcommand};
-use serde::{Deserialize, Serialize};
-use std::fs::{self, File};
-use std::io::{self, Cursor};
+use clap::{Parser, Subcommand, ValueEnum};
+use std::ffi::OsString;
+use std::net::SocketAddr;
 use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
 
 mod downloader;
-use downloader::Downloader;
+
+mod exec;
+use exec::exec_tool;
+
+mod github;
 
 mod config;
-use config::{load_cli_tools, list_available_tools, add_cli_tool, check_cli_tools_links_streaming};
+use config::{list_available_tools, add_cli_tool, check_cli_tools_links_streaming, check_platforms, DEFAULT_CHECK_TARGETS};
+
+mod doctor;
+use doctor::doctor;
+
+mod bundle;
+use bundle::{create_bundle, install_bundle};
+
+mod cache;
+mod checksum;
+
+mod install;
+use install::{install_tools, list_available_versions};
+
+mod manifest;
+
+mod mirrors;
+mod multiarch;
+
+mod platform;
+use platform::Platform;
+mod preflight;
+
+mod report;
+use report::{set_reporter, JsonReporter, SilentReporter, TerminalReporter};
+
+mod retry;
+mod rollback;
+use rollback::{list_generations, rollback};
+mod scheduler;
+use scheduler::init_scheduler;
+mod serve;
+use serve::serve;
+mod settings;
+mod shim;
+use shim::run_shim;
+mod smoke;
+mod staging;
+use staging::cleanup_on_signal;
+mod state;
+mod store;
+use store::{list_installed_versions, use_version};
 
 mod unpack;
-use unpack::extract_archive;
+mod verify;
+mod version;
+use verify::verify_tools;
 
 #[derive(Parser)]
 #[command(name = "coolclis")]
 #[command(about = "A tool to download and install CLI tools from GitHub releases", long_about = None)]
 struct Cli {
+    /// How to report progress: human-readable, JSON lines or not at all
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Terminal)]
+    output: OutputFormat,
+
+    /// Maximum combined download rate in bytes per second, e.g. 500K or 2M
+    #[arg(long, global = true)]
+    limit_rate: Option<String>,
+
     #[command(subcommand)]
     command: Commands,
 }
 
+#[derive(Clone, Copy, ValueEnum)]
+enum OutputFormat {
+    Terminal,
+    Json,
+    Silent,
+}
+
 #[derive(Subcommand)]
 enum Commands {
-    /// Install a tool from GitHub
+    /// Install tools from GitHub
     Install {
-        /// GitHub repository in the format owner/repo or a predefined tool name
-        tool: String,
+        /// GitHub repositories in the format owner/repo or predefined tool names
+        /// (defaults to the tools pinned by the nearest .coolclis.toml)
+        tools: Vec<String>,
 
-        /// Specific version to install (defaults to latest)
+        /// Version to install: a tag or version (1.2.3 finds v1.2.3), a range like ^1.4 or ~0.22,
+        /// latest or latest-prerelease (defaults to latest, only with a single tool)
         #[arg(short, long)]
         version: Option<String>,
 
         /// Installation directory (defaults to ~/.local/bin)
         #[arg(short, long)]
         dir: Option<PathBuf>,
+
+        /// Number of tools installed concurrently
+        #[arg(short, long, default_value_t = 4)]
+        jobs: usize,
+
+        /// Install the binaries for a platform in the format os-arch[-libc] (e.g. linux-arm64-musl)
+        /// as plain files into --dir, without running them
+        #[arg(long)]
+        target: Option<String>,
     },
 
     /// List all available predefined tools
@@ -56,237 +130,158 @@ enum Commands {
     },
 
     /// Check all tool links in the config file (validate GitHub repo exists)
-    Check,
-}
-
-#[derive(Debug, Deserialize, Serialize)]
-struct Release {
-    tag_name: String,
-    assets: Vec<Asset>,
-}
-
-#[derive(Debug, Deserialize, Serialize)]
-struct Asset {
-    name: String,
-    browser_download_url: String,
-    size: u64,
-}
-async fn get_latest_release(repo: &str) -> Result<Release> {
-    let downloader = Downloader::default();
-    let url = format!("https://api.github.com/repos/{}/releases/latest", repo);
-    downloader.get_json::<Release>(&url).await
-}
-
-async fn get_specific_release(repo: &str, version: &str) -> Result<Release> {
-    let downloader = Downloader::default();
-    let url = format!(
-        "https://api.github.com/repos/{}/releases/tags/{}",
-        repo, version
-    );
-    downloader.get_json::<Release>(&url).await
-}
-
-fn get_platform_info() -> (String, String) {
-    let os = if cfg!(target_os = "windows") {
-        "windows"
-    } else if cfg!(target_os = "macos") {
-        "darwin"
-    } else if cfg!(target_os = "linux") {
-        "linux"
-    } else {
-        "unknown"
-    };
+    Check {
+        /// Show which asset of the latest release each tool would install on these targets,
+        /// comma-separated (defaults to the common desktop and server targets)
+        #[arg(long, value_delimiter = ',', num_args = 0..)]
+        platforms: Option<Vec<String>>,
+    },
 
-    let arch = if cfg!(target_arch = "x86_64") {
-        "x86_64"
-    } else if cfg!(target_arch = "aarch64") {
-        "arm64"
-    } else if cfg!(target_arch = "x86") {
-        "x86"
-    } else {
-        "unknown"
-    };
+    /// Create or install bundles of tools for machines without network access
+    Bundle {
+        #[command(subcommand)]
+        command: BundleCommands,
+    },
 
-    (os.to_string(), arch.to_string())
-}
+    /// Restore the previously installed version of a tool
+    Rollback {
+        /// Name of the installed tool
+        tool: String,
 
-fn find_appropriate_asset<'a>(release: &'a Release, tool_name: &str) -> Result<&'a Asset> {
-    let (os, arch) = get_platform_info();
-
-    // Variations of OS/arch in filenames
-    let os_variations: Vec<&str> = if os == "darwin" {
-        vec!["apple-darwin", "darwin", "macos", "mac", "osx"]
-    } else if os == "windows" {
-        vec!["pc-windows", "windows"]
-    } else if os == "linux" {
-        // prefer musl over gnu
-        vec!["unknown-linux-musl", "unknown-linux", "unknown-linux-gnu", "linux"]
-    } else {
-        vec![&os]
-    };
+        /// List the versions kept for rollback instead
+        #[arg(short, long)]
+        list: bool,
+    },
 
-    let arch_variations: Vec<&str> = if arch == "x86_64" {
-        vec!["x86_64", "amd64", "x64"]
-    } else if arch == "arm64" {
-        vec!["arm64", "aarch64"]
-    } else {
-        vec![&arch]
-    };
+    /// Switch a tool to another installed version
+    Use {
+        /// Tool and version in the format tool@version
+        spec: String,
+    },
 
-    // Create combinations of search terms
-    let mut search_patterns = Vec::new();
-    for os_var in &os_variations {
-        for arch_var in &arch_variations {
-            search_patterns.push(format!("{}-{}", os_var, arch_var));
-            search_patterns.push(format!("{}_{}", os_var, arch_var));
-            search_patterns.push(format!("{}{}", os_var, arch_var));
-            search_patterns.push(format!("{}-{}", arch_var, os_var));
-        }
-        search_patterns.push(os_var.to_string()); // OS only pattern
-    }
+    /// Show the versions of a tool
+    Versions {
+        /// Name of the tool
+        tool: String,
 
-    // Extensions to look for
-    let extensions = if os == "windows" {
-        vec![".exe", ".zip", ".tar.gz", ".tgz"]
-    } else {
-        vec!["", ".tar.gz", ".tgz", ".zip"]
-    };
+        /// Show the versions in the store instead of the latest release
+        #[arg(long)]
+        installed: bool,
+    },
 
-    // First try to find assets that match the tool name
-    for pattern in &search_patterns {
-        for ext in &extensions {
-            for asset in &release.assets {
-                let name = asset.name.to_lowercase();
-                let tool_lower = tool_name.to_lowercase();
+    /// Diagnose PATH, shadowed binaries and broken installs
+    Doctor {
+        /// Installation directory (defaults to ~/.local/bin)
+        #[arg(short, long)]
+        dir: Option<PathBuf>,
+    },
 
-                if name.contains(&tool_lower) && name.contains(pattern) && name.ends_with(ext) {
-                    return Ok(asset);
-                }
-            }
-        }
-    }
+    /// Check that installed files were not modified since they were installed
+    Verify {
+        /// Names of installed tools (defaults to all of them)
+        tools: Vec<String>,
 
-    // If we couldn't find an asset matching the tool name, try to find any asset for the platform
-    for pattern in &search_patterns {
-        for ext in &extensions {
-            for asset in &release.assets {
-                let name = asset.name.to_lowercase();
-                if name.contains(pattern) && name.ends_with(ext) {
-                    return Ok(asset);
-                }
-            }
-        }
-    }
+        /// Print the results as JSON
+        #[arg(long)]
+        json: bool,
 
-    Err(anyhow!("No suitable asset found for your platform ({}-{})", os, arch))
-}
+        /// Also compare the installed assets with the checksums published with their releases
+        #[arg(long)]
+        upstream: bool,
+    },
 
-async fn install_tool(repo: &str, version: Option<&str>, dir: Option<&PathBuf>) -> Result<()> {
-    let tool = repo.split('/').next_back().unwrap();
+    /// Run a tool without installing it, downloading it into the cache on first use
+    #[command(alias = "run")]
+    Exec {
+        /// Tool in the format tool@version, where the version can also be a range like ^1.4,
+        /// tool alone for the latest release
+        spec: String,
 
-    println!("Installing {} from {}", tool, repo);
+        /// Arguments passed to the tool, after --
+        #[arg(last = true)]
+        args: Vec<OsString>,
+    },
 
-    // Get the release
-    let release = match version {
-        Some(v) => get_specific_release(repo, v).await?,
-        None => get_latest_release(repo).await?,
-    };
+    /// Run the version of a tool pinned by the nearest .coolclis.toml, used by shims
+    #[command(hide = true)]
+    Shim {
+        tool: String,
 
-    println!("Found release: {}", release.tag_name);
-
-    // Find the right asset
-    let asset = find_appropriate_asset(&release, tool)?;
-    println!("Selected asset: {} ({} bytes)", asset.name, asset.size);
-
-    // Download the asset
-    let downloader = Downloader::default();
-    let data = downloader.download_file(&asset.browser_download_url, asset.size).await?;
-
-    // Determine install directory
-    let install_dir = match dir {
-        Some(d) => d.clone(),
-        None => {
-            let mut home_dir = dirs::home_dir().ok_or_else(|| anyhow!("Could not determine home directory"))?;
-            home_dir.push(".local");
-            home_dir.push("bin");
-            fs::create_dir_all(&home_dir)?;
-            home_dir
-        }
-    };
+        #[arg(last = true)]
+        args: Vec<OsString>,
+    },
 
-    // Create a temporary directory for extraction if needed
-    let temp_dir = install_dir.join(format!("{}_temp", tool));
-    if temp_dir.exists() {
-        fs::remove_dir_all(&temp_dir)?;
-    }
-    fs::create_dir_all(&temp_dir)?;
-
-    // Check if the downloaded file is an archive that needs extraction
-    let file_path = if asset.name.ends_with(".tar.gz") || asset.name.ends_with(".tgz") || asset.name.ends_with(".zip") {
-        println!("Extracting archive...");
-
-        // Extract the archive
-        let extracted_path = extract_archive(&data, &asset.name, &temp_dir)?;
-
-        // Move the extracted binary to the final location
-        match extracted_path {
-            Some(path) => {
-                println!("Found executable: {}", path.display());
-                let dest_path = install_dir.join(tool);
-                fs::copy(path, &dest_path)?;
-                dest_path
-            },
-            None => {
-                return Err(anyhow!("Could not find executable in extracted archive"));
-            }
-        }
-    } else {
-        // It's a direct binary
-        let file_path = install_dir.join(tool);
-        let mut file = File::create(&file_path)?;
-        io::copy(&mut Cursor::new(data), &mut file)?;
-
-        // Make the file executable on Unix systems
-        #[cfg(unix)]
-        {
-            use std::os::unix::fs::PermissionsExt;
-            let metadata = fs::metadata(&file_path)?;
-            let mut perms = metadata.permissions();
-            perms.set_mode(0o755);
-            fs::set_permissions(&file_path, perms)?;
-        }
+    /// Serve release metadata and assets to other machines, caching them locally
+    Serve {
+        /// Address to listen on
+        #[arg(short, long, default_value = "127.0.0.1:8080")]
+        bind: SocketAddr,
 
-        file_path
-    };
+        /// Seconds a cached latest release is served before checking upstream again
+        #[arg(long, default_value_t = 600)]
+        latest_ttl: u64,
+    },
+}
 
-    // Clean up the temporary directory
-    fs::remove_dir_all(temp_dir)?;
+#[derive(Subcommand)]
+enum BundleCommands {
+    /// Download the latest release of tools into a bundle archive
+    Create {
+        /// GitHub repositories in the format owner/repo or predefined tool names
+        #[arg(required = true)]
+        tools: Vec<String>,
+
+        /// Comma-separated targets in the format os-arch (defaults to this platform)
+        #[arg(short, long, value_delimiter = ',')]
+        targets: Vec<String>,
+
+        /// Bundle file to write
+        #[arg(short = 'o', long, default_value = "coolclis-bundle.tar.gz")]
+        file: PathBuf,
+    },
 
-    println!("Successfully installed {} to {}", tool, file_path.display());
-    println!("Make sure {} is in your PATH", install_dir.display());
+    /// Install the tools of a bundle archive for this platform, without network access
+    Install {
+        /// Bundle file created by `bundle create`
+        file: PathBuf,
 
-    Ok(())
+        /// Installation directory (defaults to ~/.local/bin)
+        #[arg(short, long)]
+        dir: Option<PathBuf>,
+    },
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli = Cli::parse();
 
-    match &cli.command {
-        Commands::Install { tool, version, dir } => {
-            // Load the tools map
-            let tools_map = load_cli_tools()?;
+    // Command results go to stdout, progress goes to the reporter. Shims and exec must not add
+    // anything to the output of the tools they run, and `verify --json` prints nothing but its
+    // JSON document.
+    let runs_tool = matches!(cli.command, Commands::Shim { .. } | Commands::Exec { .. });
+    let output = match cli.command {
+        Commands::Verify { json: true, .. } => OutputFormat::Silent,
+        _ if runs_tool && matches!(cli.output, OutputFormat::Json) => OutputFormat::Silent,
+        _ => cli.output,
+    };
 
-            // Check if the repo is a known tool name
-            let actual_repo = if tool.contains('/') {
-                tool.to_string()
-            } else {
-                tools_map.get(tool)
-                    .ok_or_else(|| anyhow!("Unknown tool: {}. Use the 'list' command to see available tools.", tool))?
-                    .to_string()
-            };
+    match output {
+        OutputFormat::Terminal if runs_tool => set_reporter(Arc::new(TerminalReporter::on_stderr())),
+        OutputFormat::Terminal => set_reporter(Arc::new(TerminalReporter::new())),
+        OutputFormat::Json => set_reporter(Arc::new(JsonReporter)),
+        OutputFormat::Silent => set_reporter(Arc::new(SilentReporter)),
+    }
 
-            install_tool(&actual_repo, version.as_deref(), dir.as_ref()).await?;
+    // Fail early on invalid proxy, certificate or rate settings
+    init_scheduler(cli.limit_rate.as_deref())?;
+
+    // Don't leave partial installs behind when interrupted
+    tokio::spawn(cleanup_on_signal());
+
+    match &cli.command {
+        Commands::Install { tools, version, dir, jobs, target } => {
+            let target = target.as_deref().map(Platform::parse).transpose()?;
+            install_tools(tools, version.as_deref(), dir.as_ref(), *jobs, target.as_ref()).await?;
         },
         Commands::List => {
             list_available_tools()?;
@@ -305,10 +300,97 @@ async fn main() -> Result<()> {
 
             add_cli_tool(tool, repo, desc)?;
         },
-        Commands::Check => {
-            check_cli_tools_links_streaming().await?;
-        }
+        Commands::Check { platforms } => match platforms {
+            Some(targets) => {
+                let targets = if targets.is_empty() {
+                    DEFAULT_CHECK_TARGETS.iter().map(|t| Platform::parse(t)).collect::<Result<Vec<_>>>()?
+                } else {
+                    targets.iter().map(|t| Platform::parse(t)).collect::<Result<Vec<_>>>()?
+                };
+                check_platforms(&targets).await?;
+            }
+            None => check_cli_tools_links_streaming().await?,
+        },
+        Commands::Bundle { command } => match command {
+            BundleCommands::Create { tools, targets, file } => {
+                let targets = if targets.is_empty() {
+                    vec![Platform::host()]
+                } else {
+                    targets.iter().map(|t| Platform::parse(t)).collect::<Result<Vec<_>>>()?
+                };
+                create_bundle(tools, &targets, file).await?;
+            },
+            BundleCommands::Install { file, dir } => {
+                install_bundle(file, dir.as_ref())?;
+            },
+        },
+        Commands::Rollback { tool, list } => {
+            if *list {
+                list_generations(tool)?;
+            } else {
+                rollback(tool)?;
+            }
+        },
+        Commands::Use { spec } => {
+            let (tool, version) = spec.split_once('@')
+                .ok_or_else(|| anyhow!("Expected tool@version, got '{}'", spec))?;
+            use_version(tool, version)?;
+        },
+        Commands::Versions { tool, installed } => {
+            if *installed {
+                list_installed_versions(tool)?;
+            } else {
+                list_available_versions(tool).await?;
+            }
+        },
+        Commands::Doctor { dir } => {
+            doctor(dir.as_ref())?;
+        },
+        Commands::Verify { tools, json, upstream } => {
+            verify_tools(tools, *json, *upstream).await?;
+        },
+        Commands::Exec { spec, args } => {
+            exec_tool(spec, args).await?;
+        },
+        Commands::Shim { tool, args } => {
+            run_shim(tool, args).await?;
+        },
+        Commands::Serve { bind, latest_ttl } => {
+            serve(*bind, Duration::from_secs(*latest_ttl)).await?;
+        },
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn cli_arguments_are_consistent() {
+        Cli::command().debug_assert();
+    }
+
+    /// A subcommand argument with the id of a global option makes clap panic when it's read
+    #[test]
+    fn subcommands_dont_redefine_global_options() {
+        fn check(command: &clap::Command, globals: &[String]) {
+            for subcommand in command.get_subcommands() {
+                for arg in subcommand.get_arguments() {
+                    assert!(!globals.contains(&arg.get_id().to_string()),
+                        "`{}` redefines the global option `{}`", subcommand.get_name(), arg.get_id());
+                }
+                check(subcommand, globals);
+            }
+        }
+
+        let command = Cli::command();
+        let globals: Vec<String> = command.get_arguments()
+            .filter(|arg| arg.is_global_set())
+            .map(|arg| arg.get_id().to_string())
+            .collect();
+        check(&command, &globals);
+    }
+}
@@ -0,0 +1,92 @@
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::resolve_tool;
+use crate::version::VersionRequirement;
+
+/// Name of the project manifest pinning tool versions
+pub const MANIFEST_FILE: &str = ".coolclis.toml";
+
+/// Tool versions pinned by a project, for example:
+///
+/// ```toml
+/// [tools]
+/// ripgrep = "14.1.0"
+/// "sharkdp/fd" = "v10.2.0"
+/// ```
+#[derive(Debug, Default, Deserialize)]
+pub struct Manifest {
+    /// Version by predefined tool name or owner/repo
+    #[serde(default)]
+    pub tools: BTreeMap<String, String>,
+}
+
+/// A tool pinned by a manifest
+#[derive(Debug)]
+pub struct PinnedTool {
+    /// Name the tool is installed as
+    pub name: String,
+    pub repo: String,
+    pub version: String,
+}
+
+impl Manifest {
+    pub fn load(path: &Path) -> Result<Self> {
+        let manifest_str = fs::read_to_string(path)?;
+        toml::from_str(&manifest_str)
+            .map_err(|e| anyhow!("Invalid manifest {}: {}", path.display(), e))
+    }
+
+    /// The tools of the manifest, with their repositories resolved
+    pub fn pinned_tools(&self) -> Result<Vec<PinnedTool>> {
+        self.tools.iter()
+            .map(|(tool, version)| {
+                // Nothing records what a range resolved to, so the pinned version must be exact
+                if !matches!(VersionRequirement::parse(version)?, VersionRequirement::Exact(_)) {
+                    return Err(anyhow!("{} must be pinned to an exact version in {}, not {}", tool, MANIFEST_FILE, version));
+                }
+                let repo = resolve_tool(tool)?.repo;
+                Ok(PinnedTool {
+                    name: repo.split('/').next_back().unwrap().to_string(),
+                    repo,
+                    version: version.clone(),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Manifests found walking up from `dir`, nearest first
+pub fn find_manifests(dir: &Path) -> Vec<PathBuf> {
+    dir.ancestors()
+        .map(|d| d.join(MANIFEST_FILE))
+        .filter(|path| path.is_file())
+        .collect()
+}
+
+/// The nearest manifest from the current directory
+pub fn nearest_manifest() -> Result<Option<(PathBuf, Manifest)>> {
+    let current_dir = std::env::current_dir()?;
+    match find_manifests(&current_dir).into_iter().next() {
+        Some(path) => {
+            let manifest = Manifest::load(&path)?;
+            Ok(Some((path, manifest)))
+        }
+        None => Ok(None),
+    }
+}
+
+/// The version of the tool installed as `name` pinned by the nearest manifest listing it
+pub fn pinned_version(name: &str) -> Result<Option<PinnedTool>> {
+    let current_dir = std::env::current_dir()?;
+    for path in find_manifests(&current_dir) {
+        let pinned = Manifest::load(&path)?.pinned_tools()?;
+        if let Some(tool) = pinned.into_iter().find(|t| t.name == name) {
+            return Ok(Some(tool));
+        }
+    }
+    Ok(None)
+}
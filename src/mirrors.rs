@@ -0,0 +1,97 @@
+use serde::{Deserialize, Serialize};
+
+use crate::settings::settings;
+
+/// Rewrite rule sending requests for URLs under `prefix` to mirrors
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MirrorRule {
+    /// URL prefix to rewrite, e.g. `https://github.com/`
+    pub prefix: String,
+    /// Prefixes replacing `prefix`, tried in order
+    pub mirrors: Vec<String>,
+    /// Whether to fall back to the original URL when all mirrors fail
+    #[serde(default = "default_fallback_to_origin")]
+    pub fallback_to_origin: bool,
+}
+
+fn default_fallback_to_origin() -> bool {
+    true
+}
+
+/// Get the URLs to try for `url`, in order, according to the mirror rules of the settings.
+/// Only the first rule whose prefix matches is applied.
+pub fn candidate_urls(url: &str) -> Vec<String> {
+    rewrite_url(url, &settings().mirrors)
+}
+
+fn rewrite_url(url: &str, rules: &[MirrorRule]) -> Vec<String> {
+    let Some((rule, rest)) = rules.iter().find_map(|rule| url.strip_prefix(rule.prefix.as_str()).map(|rest| (rule, rest))) else {
+        return vec![url.to_string()];
+    };
+
+    let mut urls: Vec<String> = rule.mirrors.iter()
+        .map(|mirror| format!("{}{}", mirror, rest))
+        .collect();
+    if rule.fallback_to_origin || urls.is_empty() {
+        urls.push(url.to_string());
+    }
+    urls
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(prefix: &str, mirrors: &[&str], fallback_to_origin: bool) -> MirrorRule {
+        MirrorRule {
+            prefix: prefix.to_string(),
+            mirrors: mirrors.iter().map(|m| m.to_string()).collect(),
+            fallback_to_origin,
+        }
+    }
+
+    #[test]
+    fn unmatched_urls_are_kept() {
+        let rules = [rule("https://github.com/", &["https://mirror.example/gh/"], true)];
+        assert_eq!(rewrite_url("https://api.github.com/repos/a/b", &rules), ["https://api.github.com/repos/a/b"]);
+        assert_eq!(rewrite_url("https://github.com/a/b", &[]), ["https://github.com/a/b"]);
+    }
+
+    #[test]
+    fn mirrors_replace_the_prefix_in_order() {
+        let rules = [rule("https://github.com/", &["https://m1.example/", "file:///srv/mirror/"], true)];
+        assert_eq!(rewrite_url("https://github.com/a/b/releases/download/v1/b.tar.gz", &rules), [
+            "https://m1.example/a/b/releases/download/v1/b.tar.gz",
+            "file:///srv/mirror/a/b/releases/download/v1/b.tar.gz",
+            "https://github.com/a/b/releases/download/v1/b.tar.gz",
+        ]);
+    }
+
+    #[test]
+    fn origin_is_skipped_without_fallback() {
+        let rules = [rule("https://github.com/", &["https://m1.example/"], false)];
+        assert_eq!(rewrite_url("https://github.com/a/b", &rules), ["https://m1.example/a/b"]);
+    }
+
+    #[test]
+    fn origin_is_kept_when_there_are_no_mirrors() {
+        let rules = [rule("https://github.com/", &[], false)];
+        assert_eq!(rewrite_url("https://github.com/a/b", &rules), ["https://github.com/a/b"]);
+    }
+
+    #[test]
+    fn only_the_first_matching_rule_applies() {
+        let rules = [
+            rule("https://github.com/a/", &["https://a.example/"], false),
+            rule("https://github.com/", &["https://all.example/"], false),
+        ];
+        assert_eq!(rewrite_url("https://github.com/a/b", &rules), ["https://a.example/b"]);
+        assert_eq!(rewrite_url("https://github.com/c/d", &rules), ["https://all.example/c/d"]);
+    }
+
+    #[test]
+    fn prefixes_match_from_the_start() {
+        let rules = [rule("github.com/", &["https://m1.example/"], false)];
+        assert_eq!(rewrite_url("https://github.com/a/b", &rules), ["https://github.com/a/b"]);
+    }
+}
@@ -0,0 +1,282 @@
+use anyhow::{anyhow, Context, Result};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::install::{download_asset, resolve_release, run_blocking, unpack_asset, InstalledTool};
+use crate::platform::Platform;
+use crate::report::{report, warning, Event};
+use crate::settings::settings;
+use crate::smoke::probe_binary;
+use crate::staging::{write_script, StagingDir};
+use crate::state::{get_state_dir, load_state};
+
+/// Comment identifying the scripts written by `write_dispatcher`
+const DISPATCHER_MARKER: &str = "# coolclis dispatcher";
+
+/// The platforms of the `targets` setting, empty unless multi-arch installs are configured
+pub fn configured_targets() -> Result<Vec<Platform>> {
+    let mut targets: Vec<Platform> = Vec::new();
+    for target in &settings().targets {
+        let platform = Platform::parse(target)
+            .with_context(|| "Invalid target in the `targets` setting")?;
+        if platform.os == "windows" {
+            return Err(anyhow!("The `targets` setting can't include {}, dispatchers are shell scripts", target));
+        }
+        if targets.iter().any(|t| t.os == platform.os && t.arch == platform.arch) {
+            return Err(anyhow!("The `targets` setting lists {}-{} more than once, only one build per OS and architecture can be dispatched to",
+                platform.os, platform.arch));
+        }
+        targets.push(platform);
+    }
+
+    if !targets.is_empty() && cfg!(not(unix)) {
+        return Err(anyhow!("The `targets` setting is only supported on Unix"));
+    }
+    Ok(targets)
+}
+
+/// Directory holding the binaries for `platform`, `~/.local/share/coolclis/targets/<platform>/`
+pub fn target_dir(platform: &Platform) -> Result<PathBuf> {
+    Ok(get_state_dir()?.join("targets").join(platform.to_string()))
+}
+
+/// Binary of one target, unpacked next to the one it replaces
+struct StagedBinary {
+    staging: StagingDir,
+    executable: PathBuf,
+    dest: PathBuf,
+}
+
+/// Install the same release of `repo` for every target, for home directories shared by machines
+/// of different architectures. The binaries go to the directory of their target, and the bin
+/// entry in `install_dir` becomes a dispatcher running the one for the machine it runs on.
+///
+/// All the assets are downloaded and unpacked before anything is replaced, and the binaries
+/// already replaced are put back if one can't be, so every target stays on the same version.
+pub async fn install_multiarch_tool(repo: &str, version: Option<&str>, install_dir: &Path, targets: &[Platform]) -> Result<InstalledTool> {
+    let tool = repo.split('/').next_back().unwrap();
+    let link = install_dir.join(tool);
+    // The store would keep pointing its bin entry to a version that's no longer there
+    if load_state()?.tools.get(tool).is_some_and(|t| t.link == link) {
+        return Err(anyhow!("{} is installed at {} for this machine only, install it for several targets with --dir to another directory",
+            tool, link.display()));
+    }
+    if !is_dispatcher_or_missing(&link) {
+        return Err(anyhow!("{} wasn't installed by coolclis, move it away or install {} with --dir to another directory",
+            link.display(), tool));
+    }
+
+    let release = resolve_release(tool, repo, version).await?;
+
+    if !targets.iter().any(Platform::is_host) {
+        warning(format!("None of the targets is {}, {} won't run on this machine", Platform::host(), tool));
+    }
+
+    let mut downloads = Vec::new();
+    for target in targets {
+        // Only the binary for this machine can be checked before installing
+        let (asset_name, data) = download_asset(tool, &release, target, target.is_host()).await?;
+        downloads.push((target_dir(target)?, asset_name, data));
+    }
+
+    let binaries: Vec<(&Platform, PathBuf)> = targets.iter()
+        .zip(&downloads)
+        .map(|(target, (dir, _, _))| (target, dir.join(tool)))
+        .collect();
+    let host = targets.iter().position(Platform::is_host);
+    let (name, repo, version) = (tool.to_string(), repo.to_string(), release.tag_name.clone());
+    run_blocking(move || {
+        // Unpack every asset before moving any of them into place
+        let mut staged = Vec::new();
+        for (dir, asset_name, data) in downloads {
+            staged.push(stage_binary(&name, &asset_name, &data, &dir)?);
+        }
+        // The binary for this machine must run before it replaces anything
+        if let Some(binary) = host.map(|i| &staged[i]) {
+            if let Some(failure) = probe_binary(&name, &repo, &version, &binary.executable) {
+                return Err(anyhow!("Not installing {} {}: {}", name, version, failure));
+            }
+        }
+        replace_binaries(&staged)
+    }).await?;
+
+    write_script(&link, &dispatcher_script(tool, &binaries))
+        .with_context(|| format!("Failed to write the dispatcher {}", link.display()))?;
+    report(Event::Installed { tool: tool.to_string(), path: link.clone() });
+
+    Ok(InstalledTool {
+        version: release.tag_name,
+        path: link,
+    })
+}
+
+/// Unpack the asset of a target into a staging directory in `dir`, the directory of the target
+fn stage_binary(tool: &str, asset_name: &str, data: &[u8], dir: &Path) -> Result<StagedBinary> {
+    let staging = StagingDir::new(dir, tool)?;
+    let content_dir = staging.path().join("content");
+    fs::create_dir(&content_dir)?;
+    let executable = unpack_asset(tool, asset_name, data, &content_dir)?;
+    Ok(StagedBinary { staging, executable, dest: dir.join(tool) })
+}
+
+/// Move the staged binaries into place. If one of them can't be, the ones already moved are
+/// replaced by their previous version again.
+fn replace_binaries(staged: &[StagedBinary]) -> Result<()> {
+    let mut replaced = Vec::new();
+    for binary in staged {
+        match replace_binary(binary) {
+            Ok(previous) => replaced.push((binary, previous)),
+            Err(e) => {
+                for (binary, previous) in replaced.into_iter().rev() {
+                    let _ = match previous {
+                        Some(previous) => fs::rename(previous, &binary.dest),
+                        None => fs::remove_file(&binary.dest),
+                    };
+                }
+                return Err(e);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Move a staged binary into place, setting the binary it replaces aside in its staging
+/// directory. Returns where the previous binary is, if there was one.
+fn replace_binary(binary: &StagedBinary) -> Result<Option<PathBuf>> {
+    let previous = binary.staging.path().join("previous");
+    let had_previous = match fs::rename(&binary.dest, &previous) {
+        Ok(()) => true,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => false,
+        Err(e) => return Err(e).with_context(|| format!("Failed to replace {}", binary.dest.display())),
+    };
+
+    if let Err(e) = fs::rename(&binary.executable, &binary.dest) {
+        if had_previous {
+            let _ = fs::rename(&previous, &binary.dest);
+        }
+        return Err(e).with_context(|| format!("Failed to move the executable to {}", binary.dest.display()));
+    }
+    Ok(had_previous.then_some(previous))
+}
+
+/// Whether the bin entry at `link` is a dispatcher written by coolclis, or not there at all
+fn is_dispatcher_or_missing(link: &Path) -> bool {
+    if fs::symlink_metadata(link).is_err_and(|e| e.kind() == io::ErrorKind::NotFound) {
+        return true;
+    }
+    fs::read_to_string(link).is_ok_and(|script| script.lines().nth(1).is_some_and(|line| line.starts_with(DISPATCHER_MARKER)))
+}
+
+/// The dispatcher of `tool`: a script running the binary for the OS and architecture reported
+/// by `uname`
+fn dispatcher_script(tool: &str, binaries: &[(&Platform, PathBuf)]) -> String {
+    let has_armv7 = binaries.iter().any(|(t, _)| t.arch == "armv7");
+    let mut script = format!(
+        "#!/bin/sh\n{}, runs the build of {} for the architecture of this machine\ncase \"$(uname -s)-$(uname -m)\" in\n",
+        DISPATCHER_MARKER, tool,
+    );
+    for (target, executable) in binaries {
+        let systems = uname_systems(&target.os);
+        let mut machines = uname_machines(&target.arch).to_vec();
+        // ARMv6 builds also run on ARMv7 machines, when there is no ARMv7 build
+        if target.arch == "armv6" && !has_armv7 {
+            machines.extend(uname_machines("armv7"));
+        }
+        let patterns: Vec<String> = systems.iter()
+            .flat_map(|system| machines.iter().map(move |machine| format!("{}-{}", system, machine)))
+            .collect();
+        script.push_str(&format!(
+            "  {}) exec '{}' \"$@\" ;;\n",
+            patterns.join("|"),
+            executable.display().to_string().replace('\'', r"'\''"),
+        ));
+    }
+    let installed: Vec<String> = binaries.iter().map(|(t, _)| t.to_string()).collect();
+    script.push_str(&format!(
+        "esac\necho \"{}: no build for $(uname -s) $(uname -m), installed for {}\" >&2\nexit 1\n",
+        tool,
+        installed.join(", "),
+    ));
+    script
+}
+
+/// Names `uname -s` gives for an OS
+fn uname_systems(os: &str) -> &'static [&'static str] {
+    match os {
+        "linux" => &["Linux"],
+        "darwin" => &["Darwin"],
+        "freebsd" => &["FreeBSD"],
+        "netbsd" => &["NetBSD"],
+        "illumos" => &["SunOS"],
+        _ => &[],
+    }
+}
+
+/// Names `uname -m` gives for an architecture, across the supported systems
+fn uname_machines(arch: &str) -> &'static [&'static str] {
+    match arch {
+        "x86_64" => &["x86_64", "amd64", "i86pc"],
+        "arm64" => &["aarch64", "arm64"],
+        "x86" => &["i386", "i486", "i586", "i686"],
+        "armv7" => &["armv7*", "armv8l"],
+        "armv6" => &["armv6*"],
+        "armel" => &["armv5*", "armv4*"],
+        "riscv64" => &["riscv64"],
+        "ppc64le" => &["ppc64le"],
+        "s390x" => &["s390x"],
+        "loongarch64" => &["loongarch64"],
+        _ => &[],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn script(targets: &[(&str, &str)]) -> String {
+        let platforms: Vec<Platform> = targets.iter().map(|(t, _)| Platform::parse(t).unwrap()).collect();
+        let binaries: Vec<(&Platform, PathBuf)> = platforms.iter()
+            .zip(targets)
+            .map(|(platform, (_, path))| (platform, PathBuf::from(path)))
+            .collect();
+        dispatcher_script("tool", &binaries)
+    }
+
+    #[test]
+    fn one_case_per_target() {
+        let script = script(&[("linux-x86_64", "/t/linux-x86_64/tool"), ("darwin-arm64", "/t/darwin-arm64/tool")]);
+        assert!(script.starts_with(&format!("#!/bin/sh\n{}", DISPATCHER_MARKER)));
+        assert!(script.contains("  Linux-x86_64|Linux-amd64|Linux-i86pc) exec '/t/linux-x86_64/tool' \"$@\" ;;\n"));
+        assert!(script.contains("  Darwin-aarch64|Darwin-arm64) exec '/t/darwin-arm64/tool' \"$@\" ;;\n"));
+    }
+
+    #[test]
+    fn armv6_builds_run_on_armv7_without_an_armv7_build() {
+        let armv6 = script(&[("linux-armv6", "/t/armv6/tool")]);
+        assert!(armv6.contains("  Linux-armv6*|Linux-armv7*|Linux-armv8l) exec '/t/armv6/tool'"));
+
+        let both = script(&[("linux-armv6", "/t/armv6/tool"), ("linux-armv7", "/t/armv7/tool")]);
+        assert!(both.contains("  Linux-armv6*) exec '/t/armv6/tool'"));
+        assert!(both.contains("  Linux-armv7*|Linux-armv8l) exec '/t/armv7/tool'"));
+    }
+
+    #[test]
+    fn quotes_paths() {
+        let script = script(&[("linux-x86_64", "/home/o'brien/tool")]);
+        assert!(script.contains(r#"exec '/home/o'\''brien/tool' "$@" ;;"#));
+    }
+
+    #[test]
+    fn fails_on_other_machines() {
+        let script = script(&[("linux-s390x", "/t/s390x/tool"), ("illumos-x86_64", "/t/illumos/tool")]);
+        assert!(script.ends_with("esac\necho \"tool: no build for $(uname -s) $(uname -m), installed for linux-s390x, illumos-x86_64\" >&2\nexit 1\n"));
+
+        #[cfg(all(unix, not(target_arch = "s390x")))]
+        {
+            let output = std::process::Command::new("sh").arg("-c").arg(&script).output().unwrap();
+            assert_eq!(output.status.code(), Some(1));
+            assert!(String::from_utf8_lossy(&output.stderr).starts_with("tool: no build for "));
+        }
+    }
+}
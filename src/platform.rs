@@ -0,0 +1,648 @@
+use anyhow::{anyhow, Result};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::sync::OnceLock;
+
+use crate::github::{Asset, Release};
+use crate::settings::{settings, PreferLibc};
+
+/// Operating system and architecture a binary is built for, e.g. `linux-x86_64`,
+/// and for Linux optionally the libc, e.g. `linux-arm64-musl`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Platform {
+    pub os: String,
+    pub arch: String,
+    /// Only builds for this libc are used, otherwise the preferred one comes first
+    pub libc: Option<LinuxBuild>,
+}
+
+/// Operating systems and the names they go by in targets
+const OS_ALIASES: &[(&str, &[&str])] = &[
+    ("linux", &["linux"]),
+    ("darwin", &["darwin", "macos", "mac", "osx", "apple"]),
+    ("windows", &["windows", "win"]),
+    ("freebsd", &["freebsd"]),
+    ("netbsd", &["netbsd"]),
+    ("illumos", &["illumos", "solaris", "sunos"]),
+];
+
+/// Architectures and the names they go by in targets and asset names, most common first.
+/// `armv7` and `armv6` are hard-float, `armel` is soft-float.
+const ARCH_ALIASES: &[(&str, &[&str])] = &[
+    ("x86_64", &["x86_64", "amd64", "x64"]),
+    ("arm64", &["arm64", "aarch64"]),
+    ("x86", &["i686", "i586", "i386", "386", "x86"]),
+    ("armv7", &["armv7", "armv7l", "armv7hf", "armhf"]),
+    ("armv6", &["armv6", "armv6l", "armv6hf", "arm"]),
+    ("armel", &["armel", "armv5te", "armv5"]),
+    ("riscv64", &["riscv64", "riscv64gc"]),
+    ("ppc64le", &["ppc64le", "powerpc64le"]),
+    ("s390x", &["s390x"]),
+    ("loongarch64", &["loongarch64", "loong64"]),
+];
+
+fn canonical_name(aliases: &'static [(&'static str, &'static [&'static str])], name: &str) -> Option<&'static str> {
+    aliases.iter()
+        .find(|(_, names)| names.contains(&name))
+        .map(|(canonical, _)| *canonical)
+}
+
+static HOST: OnceLock<Platform> = OnceLock::new();
+
+impl Platform {
+    /// The platform coolclis is running on, detected once on first use
+    pub fn host() -> Self {
+        HOST.get_or_init(|| {
+            let os = if cfg!(target_os = "windows") {
+                "windows"
+            } else if cfg!(target_os = "macos") {
+                "darwin"
+            } else if cfg!(target_os = "linux") {
+                "linux"
+            } else if cfg!(target_os = "freebsd") {
+                "freebsd"
+            } else if cfg!(target_os = "netbsd") {
+                "netbsd"
+            } else if cfg!(any(target_os = "illumos", target_os = "solaris")) {
+                "illumos"
+            } else {
+                "unknown"
+            };
+
+            let arch = if cfg!(target_arch = "x86_64") {
+                "x86_64"
+            } else if cfg!(target_arch = "aarch64") {
+                "arm64"
+            } else if cfg!(target_arch = "x86") {
+                "x86"
+            } else if cfg!(target_arch = "arm") {
+                detect_arm()
+            } else if cfg!(target_arch = "riscv64") {
+                "riscv64"
+            } else if cfg!(all(target_arch = "powerpc64", target_endian = "little")) {
+                "ppc64le"
+            } else if cfg!(target_arch = "s390x") {
+                "s390x"
+            } else if cfg!(target_arch = "loongarch64") {
+                "loongarch64"
+            } else {
+                "unknown"
+            };
+
+            Self {
+                os: os.to_string(),
+                arch: arch.to_string(),
+                libc: None,
+            }
+        }).clone()
+    }
+
+    /// Parse a target in the `os-arch[-libc]` format, accepting common aliases (`macos-aarch64`,
+    /// `linux-amd64`, `linux-armhf`...), or a Rust target triple like `arm-unknown-linux-gnueabihf`
+    pub fn parse(target: &str) -> Result<Self> {
+        let lower = target.to_lowercase();
+        let parts: Vec<&str> = lower.split('-').collect();
+        if canonical_name(OS_ALIASES, parts[0]).is_none() && parts.len() > 2 {
+            return Self::parse_triple(target, &parts);
+        }
+        let (os, arch, libc) = match parts[..] {
+            [os, arch] => (os, arch, None),
+            [os, arch, libc] => (os, arch, Some(libc)),
+            _ => return Err(anyhow!("Invalid target '{}', expected the format os-arch[-libc] (e.g. linux-x86_64)", target)),
+        };
+
+        let os = canonical_name(OS_ALIASES, os)
+            .ok_or_else(|| anyhow!("Unsupported OS '{}' in target '{}'", os, target))?;
+        let arch = canonical_name(ARCH_ALIASES, arch)
+            .ok_or_else(|| anyhow!("Unsupported architecture '{}' in target '{}'", arch, target))?;
+        let libc = match libc {
+            None => None,
+            Some("gnu" | "glibc") if os == "linux" => Some(LinuxBuild::Gnu),
+            Some("musl") if os == "linux" => Some(LinuxBuild::Musl),
+            Some(libc) => return Err(anyhow!("Unsupported libc '{}' in target '{}', expected gnu or musl for linux", libc, target)),
+        };
+
+        Ok(Self {
+            os: os.to_string(),
+            arch: arch.to_string(),
+            libc,
+        })
+    }
+
+    /// Whether binaries for this platform run on this machine
+    pub fn is_host(&self) -> bool {
+        let host = Self::host();
+        self.os == host.os && self.arch == host.arch
+    }
+
+    /// Parse a target triple, `arch-vendor-os[-env]`
+    fn parse_triple(target: &str, parts: &[&str]) -> Result<Self> {
+        let os_index = parts.iter()
+            .position(|part| ["linux", "darwin", "windows", "freebsd", "netbsd", "illumos", "solaris"].contains(part))
+            .ok_or_else(|| anyhow!("Unsupported OS in target '{}'", target))?;
+        let os = canonical_name(OS_ALIASES, parts[os_index]).unwrap();
+        let env = parts.get(os_index + 1).copied().unwrap_or("");
+        // Only Linux targets name a libc, `x86_64-pc-windows-gnu` is about the toolchain
+        let libc = if os != "linux" {
+            None
+        } else if env.starts_with("gnu") {
+            Some(LinuxBuild::Gnu)
+        } else if env.starts_with("musl") {
+            Some(LinuxBuild::Musl)
+        } else {
+            None
+        };
+
+        // `arm-*-gnueabihf` targets ARMv6 with hard floats, `arm-*-gnueabi` soft floats
+        let arch = match parts[0] {
+            "arm" if env.ends_with("eabi") => Some("armel"),
+            arch => canonical_name(ARCH_ALIASES, arch),
+        }
+        .ok_or_else(|| anyhow!("Unsupported architecture '{}' in target '{}'", parts[0], target))?;
+
+        Ok(Self {
+            os: os.to_string(),
+            arch: arch.to_string(),
+            libc,
+        })
+    }
+}
+
+/// ARM version and float ABI of this machine: `armv7` or `armv6` with hard floats,
+/// `armel` with soft floats
+fn detect_arm() -> &'static str {
+    let machine = Command::new("uname").arg("-m").output()
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        .unwrap_or_default();
+
+    // The dynamic loaders of hard-float systems, if the system has only soft-float ones
+    // binaries must use soft floats
+    let hard_float_loader = ["/lib/ld-linux-armhf.so.3", "/lib/ld-musl-armhf.so.1"]
+        .iter()
+        .any(|path| Path::new(path).exists());
+    let soft_float_loader = ["/lib/ld-linux.so.3", "/lib/ld-musl-arm.so.1"]
+        .iter()
+        .any(|path| Path::new(path).exists());
+    if soft_float_loader && !hard_float_loader {
+        return "armel";
+    }
+
+    if machine.starts_with("armv7") || machine.starts_with("armv8") {
+        "armv7"
+    } else if machine.starts_with("armv5") {
+        "armel"
+    } else {
+        "armv6"
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.os, self.arch)?;
+        if let Some(libc) = self.libc {
+            write!(f, "-{}", libc)?;
+        }
+        Ok(())
+    }
+}
+
+/// C library of a Linux system, with its version if known
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Libc {
+    Glibc(String),
+    Musl(Option<String>),
+    Unknown,
+}
+
+impl fmt::Display for Libc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Libc::Glibc(version) => write!(f, "glibc {}", version),
+            Libc::Musl(Some(version)) => write!(f, "musl {}", version),
+            Libc::Musl(None) => write!(f, "musl"),
+            Libc::Unknown => write!(f, "an unknown libc"),
+        }
+    }
+}
+
+static HOST_LIBC: OnceLock<Libc> = OnceLock::new();
+
+/// The C library of the system coolclis is running on, detected once on first use
+pub fn host_libc() -> &'static Libc {
+    HOST_LIBC.get_or_init(detect_libc)
+}
+
+fn detect_libc() -> Libc {
+    if !cfg!(target_os = "linux") {
+        return Libc::Unknown;
+    }
+
+    // Prints `glibc 2.36` on glibc systems
+    if let Ok(output) = Command::new("getconf").arg("GNU_LIBC_VERSION").output() {
+        let output = String::from_utf8_lossy(&output.stdout);
+        if let Some(version) = output.trim().strip_prefix("glibc ") {
+            return Libc::Glibc(version.to_string());
+        }
+    }
+
+    // glibc prints `ldd (GNU libc) 2.36` on stdout, musl prints `musl libc` and `Version 1.2.4`
+    // on stderr
+    if let Ok(output) = Command::new("ldd").arg("--version").output() {
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if stderr.contains("musl") {
+            let version = stderr.lines()
+                .find_map(|line| line.strip_prefix("Version "))
+                .map(|v| v.trim().to_string());
+            return Libc::Musl(version);
+        }
+        let first_line = stdout.lines().next().unwrap_or("");
+        if first_line.contains("GLIBC") || first_line.contains("GNU libc") {
+            if let Some(version) = first_line.split_whitespace().next_back() {
+                return Libc::Glibc(version.to_string());
+            }
+        }
+    }
+
+    // The musl dynamic loader, e.g. /lib/ld-musl-x86_64.so.1
+    let has_musl_loader = fs::read_dir("/lib").is_ok_and(|entries| {
+        entries.filter_map(|e| e.ok()).any(|e| e.file_name().to_string_lossy().starts_with("ld-musl-"))
+    });
+    if has_musl_loader {
+        return Libc::Musl(None);
+    }
+
+    Libc::Unknown
+}
+
+/// Flavor of a Linux build, by the C library it's linked with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinuxBuild {
+    Gnu,
+    Musl,
+}
+
+impl fmt::Display for LinuxBuild {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LinuxBuild::Gnu => write!(f, "gnu"),
+            LinuxBuild::Musl => write!(f, "musl"),
+        }
+    }
+}
+
+/// The Linux builds to prefer for `platform` when a release has both, and why
+pub fn preferred_libc(platform: &Platform) -> (LinuxBuild, String) {
+    if let Some(libc) = platform.libc {
+        return (libc, format!("the target is {}", platform));
+    }
+    match settings().prefer_libc {
+        PreferLibc::Gnu => return (LinuxBuild::Gnu, "prefer_libc is set to gnu".to_string()),
+        PreferLibc::Musl => return (LinuxBuild::Musl, "prefer_libc is set to musl".to_string()),
+        PreferLibc::Auto => {}
+    }
+
+    if !platform.is_host() {
+        return (LinuxBuild::Musl, format!("musl builds run on any {} system", platform));
+    }
+    match host_libc() {
+        libc @ Libc::Glibc(_) => (LinuxBuild::Gnu, format!("this system uses {}", libc)),
+        libc @ Libc::Musl(_) => (LinuxBuild::Musl, format!("this system uses {}", libc)),
+        Libc::Unknown => (LinuxBuild::Musl, "the libc of this system is unknown, musl builds are static".to_string()),
+    }
+}
+
+/// Extensions of release assets that are never the executable
+const NON_EXECUTABLE_EXTENSIONS: &[&str] = &[
+    ".sha256", ".sha512", ".sha256sum", ".md5", ".sig", ".asc", ".pem", ".sbom", ".json", ".txt",
+    ".deb", ".rpm", ".apk", ".msi", ".pkg", ".dmg",
+];
+
+/// Extensions of archives and compressed files, which are not a bare executable
+const ARCHIVE_EXTENSIONS: &[&str] = &[
+    ".tar.gz", ".tgz", ".zip", ".tar.xz", ".txz", ".tar.bz2", ".tbz", ".tar.zst", ".gz", ".xz", ".bz2", ".zst", ".7z",
+];
+
+pub fn find_appropriate_asset<'a>(release: &'a Release, tool_name: &str, platform: &Platform) -> Result<&'a Asset> {
+    find_candidate_assets(release, tool_name, platform)
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow!("No suitable asset found for your platform ({})", platform))
+}
+
+/// Assets of a release that may be the executable for `platform`, best match first
+pub fn find_candidate_assets<'a>(release: &'a Release, tool_name: &str, platform: &Platform) -> Vec<&'a Asset> {
+    rank_candidate_assets(release, tool_name, platform)
+        .into_iter()
+        .map(|(_, asset)| asset)
+        .collect()
+}
+
+/// Assets of a release that may be the executable for `platform` with their rank, best match
+/// first. Assets matched by the same pattern share a rank: only their order in the release
+/// tells them apart.
+pub fn rank_candidate_assets<'a>(release: &'a Release, tool_name: &str, platform: &Platform) -> Vec<(usize, &'a Asset)> {
+    let (os, arch) = (&platform.os, &platform.arch);
+
+    // Variations of OS/arch in filenames
+    let os_variations: Vec<String> = if os == "darwin" {
+        ["apple-darwin", "darwin", "macos", "mac", "osx"].map(String::from).to_vec()
+    } else if os == "windows" {
+        ["pc-windows", "windows"].map(String::from).to_vec()
+    } else if os == "linux" {
+        // ARM builds name their float ABI, e.g. `arm-unknown-linux-gnueabihf`
+        let abi = match arch.as_str() {
+            "armv7" | "armv6" => "eabihf",
+            "armel" => "eabi",
+            _ => "",
+        };
+        let (first, second) = match preferred_libc(platform).0 {
+            LinuxBuild::Gnu => ("gnu", "musl"),
+            LinuxBuild::Musl => ("musl", "gnu"),
+        };
+        vec![
+            format!("unknown-linux-{}{}", first, abi),
+            format!("unknown-linux-{}{}", second, abi),
+            "unknown-linux".to_string(),
+            "linux".to_string(),
+        ]
+    } else if os == "illumos" {
+        ["unknown-illumos", "illumos", "sun-solaris", "solaris"].map(String::from).to_vec()
+    } else {
+        vec![format!("unknown-{}", os), os.to_string()]
+    };
+
+    let arch_variations = arch_variations(arch);
+
+    // Create combinations of search terms
+    let mut search_patterns = Vec::new();
+    for os_var in &os_variations {
+        for arch_var in &arch_variations {
+            search_patterns.push(format!("{}-{}", os_var, arch_var));
+            search_patterns.push(format!("{}_{}", os_var, arch_var));
+            search_patterns.push(format!("{}{}", os_var, arch_var));
+            search_patterns.push(format!("{}-{}", arch_var, os_var));
+        }
+        search_patterns.push(os_var.clone()); // OS only pattern
+    }
+
+    // Extensions to look for, the empty one being a bare executable
+    let extensions = if os == "windows" {
+        vec![".exe", ".zip", ".tar.gz", ".tgz"]
+    } else {
+        vec![".tar.gz", ".tgz", ".zip", ""]
+    };
+
+    // A target with a libc excludes the builds for the other one
+    let excluded_libc = match platform.libc {
+        Some(LinuxBuild::Gnu) => Some("musl"),
+        Some(LinuxBuild::Musl) => Some("gnu"),
+        None => None,
+    };
+    let assets: Vec<&Asset> = release.assets.iter()
+        .filter(|asset| {
+            let name = asset.name.to_lowercase();
+            !NON_EXECUTABLE_EXTENSIONS.iter().any(|ext| name.ends_with(ext))
+                && !excluded_libc.is_some_and(|libc| name.contains(libc))
+        })
+        .collect();
+    let mut candidates: Vec<(usize, &Asset)> = Vec::new();
+
+    // Assets that match the tool name come first, then any asset for the platform
+    let tool_lower = tool_name.to_lowercase();
+    let mut rank = 0;
+    for require_tool_name in [true, false] {
+        for pattern in &search_patterns {
+            for ext in &extensions {
+                rank += 1;
+                for asset in &assets {
+                    let name = asset.name.to_lowercase();
+                    // An OS only pattern must not pick the build of another architecture
+                    let is_os_only = os_variations.contains(pattern);
+                    let matches = (!require_tool_name || name.contains(&tool_lower))
+                        && contains_token(&name, pattern) && has_extension(&name, ext)
+                        && !(is_os_only && names_other_arch(&name, arch));
+                    if matches && !candidates.iter().any(|(_, c)| std::ptr::eq(*c, *asset)) {
+                        candidates.push((rank, asset));
+                    }
+                }
+            }
+        }
+    }
+
+    candidates
+}
+
+/// Whether `name` ends with `ext`, or is not an archive if `ext` is empty
+fn has_extension(name: &str, ext: &str) -> bool {
+    if ext.is_empty() {
+        !ARCHIVE_EXTENSIONS.iter().any(|archive| name.ends_with(archive))
+    } else {
+        name.ends_with(ext)
+    }
+}
+
+/// Names of an architecture in asset names, followed by those of older architectures it can run
+fn arch_variations(arch: &str) -> Vec<String> {
+    let compatible: &[&str] = match arch {
+        "armv7" => &["armv7", "armv6"],
+        _ => &[arch],
+    };
+    let mut variations: Vec<String> = ARCH_ALIASES.iter()
+        .filter(|(canonical, _)| compatible.contains(canonical))
+        .flat_map(|(_, names)| names.iter().map(|n| n.to_string()))
+        .collect();
+    // Soft-float builds are named like `arm-unknown-linux-gnueabi`
+    if arch == "armel" {
+        variations.push("arm".to_string());
+    }
+    if variations.is_empty() {
+        variations.push(arch.to_string());
+    }
+    variations
+}
+
+/// Whether `name` contains `token` as a whole word: not preceded or followed by a letter or
+/// digit, and not followed by `_64` (so that `x86` doesn't match `x86_64`)
+fn contains_token(name: &str, token: &str) -> bool {
+    name.match_indices(token).any(|(start, _)| {
+        let before = name[..start].chars().next_back();
+        let after = &name[start + token.len()..];
+        !before.is_some_and(|c| c.is_ascii_alphanumeric())
+            && !after.starts_with(|c: char| c.is_ascii_alphanumeric())
+            && !after.starts_with("_64")
+    })
+}
+
+/// Whether an asset name mentions an architecture other than `arch` or the ones it can run
+fn names_other_arch(name: &str, arch: &str) -> bool {
+    let own = arch_variations(arch);
+    ARCH_ALIASES.iter()
+        .flat_map(|(_, names)| names.iter())
+        .filter(|alias| !own.iter().any(|o| o == *alias))
+        .any(|alias| contains_token(name, alias))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn platform(os: &str, arch: &str, libc: Option<LinuxBuild>) -> Platform {
+        Platform { os: os.to_string(), arch: arch.to_string(), libc }
+    }
+
+    fn release(assets: &[&str]) -> Release {
+        Release {
+            tag_name: "v1.0.0".to_string(),
+            assets: assets.iter().map(|name| Asset {
+                name: name.to_string(),
+                browser_download_url: format!("https://example.com/{}", name),
+                size: 1,
+            }).collect(),
+            prerelease: false,
+            draft: false,
+        }
+    }
+
+    fn candidates(assets: &[&str], tool: &str, platform: &Platform) -> Vec<String> {
+        let release = release(assets);
+        find_candidate_assets(&release, tool, platform).iter().map(|a| a.name.clone()).collect()
+    }
+
+    #[test]
+    fn parse_targets() {
+        for (target, expected) in [
+            ("linux-x86_64", platform("linux", "x86_64", None)),
+            ("linux-amd64", platform("linux", "x86_64", None)),
+            ("Linux-AArch64", platform("linux", "arm64", None)),
+            ("linux-armhf", platform("linux", "armv7", None)),
+            ("linux-x86_64-musl", platform("linux", "x86_64", Some(LinuxBuild::Musl))),
+            ("linux-arm64-glibc", platform("linux", "arm64", Some(LinuxBuild::Gnu))),
+            ("macos-aarch64", platform("darwin", "arm64", None)),
+            ("osx-x64", platform("darwin", "x86_64", None)),
+            ("win-386", platform("windows", "x86", None)),
+            ("sunos-amd64", platform("illumos", "x86_64", None)),
+        ] {
+            assert_eq!(Platform::parse(target).unwrap(), expected, "{}", target);
+        }
+    }
+
+    #[test]
+    fn parse_invalid_targets() {
+        for target in ["linux", "linux-sparc", "plan9-x86_64", "linux-x86_64-uclibc", "darwin-arm64-musl", "linux-x86_64-musl-extra"] {
+            assert!(Platform::parse(target).is_err(), "{}", target);
+        }
+    }
+
+    #[test]
+    fn parse_target_triples() {
+        for (target, expected) in [
+            ("x86_64-unknown-linux-gnu", platform("linux", "x86_64", Some(LinuxBuild::Gnu))),
+            ("aarch64-unknown-linux-musl", platform("linux", "arm64", Some(LinuxBuild::Musl))),
+            ("armv7-unknown-linux-gnueabihf", platform("linux", "armv7", Some(LinuxBuild::Gnu))),
+            ("arm-unknown-linux-gnueabihf", platform("linux", "armv6", Some(LinuxBuild::Gnu))),
+            ("arm-unknown-linux-musleabi", platform("linux", "armel", Some(LinuxBuild::Musl))),
+            ("aarch64-apple-darwin", platform("darwin", "arm64", None)),
+            ("x86_64-pc-windows-msvc", platform("windows", "x86_64", None)),
+            ("x86_64-pc-windows-gnu", platform("windows", "x86_64", None)),
+            ("i686-pc-windows-gnu", platform("windows", "x86", None)),
+            ("x86_64-unknown-freebsd", platform("freebsd", "x86_64", None)),
+            ("x86_64-unknown-illumos", platform("illumos", "x86_64", None)),
+        ] {
+            assert_eq!(Platform::parse(target).unwrap(), expected, "{}", target);
+        }
+        assert!(Platform::parse("mips-unknown-linux-gnu").is_err());
+        assert!(Platform::parse("x86_64-unknown-plan9-gnu").is_err());
+    }
+
+    /// Targets are shown as they are parsed, so that bundles and settings can store them
+    #[test]
+    fn display_round_trips() {
+        for target in [
+            "linux-x86_64", "linux-arm64-musl", "linux-armel-gnu", "darwin-arm64", "windows-x86_64",
+            "x86_64-pc-windows-gnu", "armv7-unknown-linux-musleabihf", "macos-amd64",
+        ] {
+            let parsed = Platform::parse(target).unwrap();
+            assert_eq!(Platform::parse(&parsed.to_string()).unwrap(), parsed, "{}", target);
+        }
+        assert_eq!(Platform::parse("x86_64-pc-windows-gnu").unwrap().to_string(), "windows-x86_64");
+        assert_eq!(Platform::parse("aarch64-unknown-linux-musl").unwrap().to_string(), "linux-arm64-musl");
+    }
+
+    #[test]
+    fn tokens_match_whole_words() {
+        assert!(contains_token("tool-linux-x86_64.tar.gz", "linux"));
+        assert!(contains_token("tool-linux-x86_64.tar.gz", "x86_64"));
+        assert!(contains_token("tool_x86", "x86"));
+        assert!(!contains_token("tool-linux-x86_64.tar.gz", "x86"));
+        assert!(!contains_token("tool-linux-arm64.tar.gz", "arm"));
+        assert!(!contains_token("tool-linux-armv7.tar.gz", "arm"));
+        assert!(!contains_token("tool-darwin-amd64", "darwin-amd6"));
+        assert!(!contains_token("tool-gnulinux-amd64", "linux"));
+    }
+
+    #[test]
+    fn rank_assets() {
+        let assets = [
+            "tool-v1.0.0-x86_64-unknown-linux-gnu.tar.gz",
+            "tool-v1.0.0-x86_64-unknown-linux-musl.tar.gz",
+            "tool-v1.0.0-aarch64-unknown-linux-musl.tar.gz",
+            "tool-v1.0.0-x86_64-apple-darwin.tar.gz",
+            "tool-v1.0.0-x86_64-pc-windows-msvc.zip",
+            "tool-v1.0.0-x86_64-unknown-linux-musl.tar.gz.sha256",
+        ];
+        let linux_musl = platform("linux", "x86_64", Some(LinuxBuild::Musl));
+        assert_eq!(candidates(&assets, "tool", &linux_musl), ["tool-v1.0.0-x86_64-unknown-linux-musl.tar.gz"]);
+
+        let linux_gnu = platform("linux", "x86_64", Some(LinuxBuild::Gnu));
+        assert_eq!(candidates(&assets, "tool", &linux_gnu), ["tool-v1.0.0-x86_64-unknown-linux-gnu.tar.gz"]);
+
+        let linux_arm64 = platform("linux", "arm64", Some(LinuxBuild::Musl));
+        assert_eq!(candidates(&assets, "tool", &linux_arm64), ["tool-v1.0.0-aarch64-unknown-linux-musl.tar.gz"]);
+
+        assert_eq!(candidates(&assets, "tool", &platform("darwin", "x86_64", None)), ["tool-v1.0.0-x86_64-apple-darwin.tar.gz"]);
+        assert_eq!(candidates(&assets, "tool", &platform("windows", "x86_64", None)), ["tool-v1.0.0-x86_64-pc-windows-msvc.zip"]);
+        assert!(candidates(&assets, "tool", &platform("darwin", "arm64", None)).is_empty());
+    }
+
+    #[test]
+    fn rank_assets_prefers_the_tool_name() {
+        let assets = ["helper_linux_amd64", "tool_linux_amd64"];
+        let release = release(&assets);
+        let ranked = rank_candidate_assets(&release, "tool", &platform("linux", "x86_64", Some(LinuxBuild::Gnu)));
+        let names: Vec<&str> = ranked.iter().map(|(_, a)| a.name.as_str()).collect();
+        assert_eq!(names, ["tool_linux_amd64", "helper_linux_amd64"]);
+        assert!(ranked[0].0 < ranked[1].0);
+    }
+
+    /// Assets matched by the same pattern share their rank, the caller has to choose
+    #[test]
+    fn rank_assets_ties() {
+        let assets = ["tool-linux-amd64", "tool-server-linux-amd64"];
+        let release = release(&assets);
+        let ranked = rank_candidate_assets(&release, "tool", &platform("linux", "x86_64", Some(LinuxBuild::Gnu)));
+        assert_eq!(ranked.len(), 2);
+        assert_eq!(ranked[0].0, ranked[1].0);
+    }
+
+    #[test]
+    fn os_only_assets_skip_other_architectures() {
+        let assets = ["tool-linux-arm64", "tool-linux"];
+        let x86_64 = platform("linux", "x86_64", Some(LinuxBuild::Gnu));
+        assert_eq!(candidates(&assets, "tool", &x86_64), ["tool-linux"]);
+    }
+
+    #[test]
+    fn armv7_falls_back_to_armv6() {
+        let assets = ["tool-arm-unknown-linux-gnueabihf.tar.gz", "tool-armv7-unknown-linux-gnueabihf.tar.gz"];
+        let armv7 = platform("linux", "armv7", Some(LinuxBuild::Gnu));
+        assert_eq!(candidates(&assets, "tool", &armv7), [
+            "tool-armv7-unknown-linux-gnueabihf.tar.gz",
+            "tool-arm-unknown-linux-gnueabihf.tar.gz",
+        ]);
+        let armv6 = platform("linux", "armv6", Some(LinuxBuild::Gnu));
+        assert_eq!(candidates(&assets, "tool", &armv6), ["tool-arm-unknown-linux-gnueabihf.tar.gz"]);
+    }
+}
@@ -0,0 +1,277 @@
+use anyhow::{anyhow, Result};
+use goblin::elf::{header, Elf};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::platform::{host_libc, Libc, Platform};
+use crate::staging::StagingDir;
+use crate::unpack::extract_archive;
+
+/// Check that the executable of a downloaded asset can run on this system, before installing it.
+/// Only Linux executables are inspected: their ELF header must match the architecture of the
+/// host, and the dynamic loader, glibc version and shared libraries they need must be there.
+pub fn preflight_asset(tool: &str, asset_name: &str, data: &[u8]) -> Result<()> {
+    if Platform::host().os != "linux" {
+        return Ok(());
+    }
+
+    let is_archive = asset_name.ends_with(".tar.gz") || asset_name.ends_with(".tgz") || asset_name.ends_with(".zip");
+    let problems = if is_archive {
+        // Extracted aside, libraries shipped next to the executable count as present
+        let staging = StagingDir::new(&std::env::temp_dir(), tool)?;
+        let Some(executable) = extract_archive(data, asset_name, staging.path(), tool)? else {
+            return Ok(());
+        };
+        executable_problems(&fs::read(&executable)?, executable.parent())
+    } else {
+        executable_problems(data, None)
+    };
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!("{}", problems.join("; ")))
+    }
+}
+
+/// Reasons the executable `data`, extracted into `origin` if it comes from an archive, can't run
+/// on this system. Files that are not executables, like scripts, can't be checked.
+fn executable_problems(data: &[u8], origin: Option<&Path>) -> Vec<String> {
+    if data.starts_with(b"MZ") {
+        return vec!["it's a Windows executable".to_string()];
+    }
+    let mach_o_magics: [&[u8]; 4] = [b"\xfe\xed\xfa\xce", b"\xfe\xed\xfa\xcf", b"\xcf\xfa\xed\xfe", b"\xca\xfe\xba\xbe"];
+    if mach_o_magics.iter().any(|magic| data.starts_with(magic)) {
+        return vec!["it's a macOS executable".to_string()];
+    }
+    if !data.starts_with(b"\x7fELF") {
+        return Vec::new();
+    }
+
+    let elf = match Elf::parse(data) {
+        Ok(elf) => elf,
+        Err(e) => return vec![format!("its ELF header is invalid: {}", e)],
+    };
+    let mut problems = Vec::new();
+
+    let host = Platform::host();
+    if let Some((machine, is_64)) = host_machine(&host.arch) {
+        if elf.header.e_machine != machine {
+            problems.push(format!("it's built for {}, not {}", header::machine_to_str(elf.header.e_machine), host.arch));
+        } else if elf.is_64 != is_64 {
+            problems.push(format!("it's a {}-bit executable", if elf.is_64 { 64 } else { 32 }));
+        } else if !elf.little_endian && host.arch != "s390x" {
+            problems.push("it's a big-endian executable".to_string());
+        } else if machine == header::EM_ARM && elf.interpreter.is_some() {
+            // Dynamically linked ARM executables must use the float ABI of the system
+            let hard_float = elf.header.e_flags & EF_ARM_ABI_FLOAT_HARD != 0;
+            let soft_float = elf.header.e_flags & EF_ARM_ABI_FLOAT_SOFT != 0;
+            if hard_float && host.arch == "armel" {
+                problems.push("it uses hard floats, this system uses soft floats".to_string());
+            } else if soft_float && host.arch != "armel" {
+                problems.push("it uses soft floats, this system uses hard floats".to_string());
+            }
+        }
+    }
+    if elf.header.e_type == header::ET_REL
+        || (elf.header.e_type == header::ET_DYN && elf.interpreter.is_none() && elf.entry == 0)
+    {
+        problems.push("it's a library or an object file, not an executable".to_string());
+    }
+    if !problems.is_empty() {
+        return problems;
+    }
+
+    if let Some(interpreter) = elf.interpreter {
+        if !Path::new(interpreter).exists() {
+            problems.push(format!("it needs the dynamic loader {}, which {} doesn't have", interpreter, host_description()));
+        }
+    }
+
+    if let Some(needed) = required_glibc(&elf) {
+        match host_libc() {
+            Libc::Glibc(version) if compare_versions(version, &needed).is_lt() => {
+                problems.push(format!("it needs GLIBC_{}, this system has glibc {}", needed, version));
+            }
+            Libc::Musl(_) => problems.push(format!("it needs GLIBC_{}, this system uses musl", needed)),
+            _ => {}
+        }
+    }
+
+    let dirs = library_dirs(&elf, origin);
+    let missing: Vec<&str> = elf.libraries.iter()
+        .copied()
+        .filter(|library| !dirs.iter().any(|dir| dir.join(library).exists()))
+        .collect();
+    if !missing.is_empty() {
+        problems.push(format!("it needs shared libraries this system doesn't have: {}", missing.join(", ")));
+    }
+
+    problems
+}
+
+/// Float ABI flags of ARM executables
+const EF_ARM_ABI_FLOAT_SOFT: u32 = 0x200;
+const EF_ARM_ABI_FLOAT_HARD: u32 = 0x400;
+
+/// ELF machine and bitness of executables for an architecture
+fn host_machine(arch: &str) -> Option<(u16, bool)> {
+    match arch {
+        "x86_64" => Some((header::EM_X86_64, true)),
+        "arm64" => Some((header::EM_AARCH64, true)),
+        "x86" => Some((header::EM_386, false)),
+        "armv7" | "armv6" | "armel" => Some((header::EM_ARM, false)),
+        "riscv64" => Some((header::EM_RISCV, true)),
+        "ppc64le" => Some((header::EM_PPC64, true)),
+        "s390x" => Some((header::EM_S390, true)),
+        "loongarch64" => Some((header::EM_LOONGARCH, true)),
+        _ => None,
+    }
+}
+
+fn host_description() -> String {
+    match host_libc() {
+        Libc::Unknown => "this system".to_string(),
+        libc => format!("this system ({})", libc),
+    }
+}
+
+/// Highest `GLIBC_x.y` symbol version required by an executable, if it's linked with glibc
+fn required_glibc(elf: &Elf) -> Option<String> {
+    let verneed = elf.verneed.as_ref()?;
+    highest_glibc(verneed.iter()
+        .flat_map(|need| need.iter().filter_map(|aux| elf.dynstrtab.get_at(aux.vna_name)).collect::<Vec<_>>()))
+}
+
+/// Highest glibc version among symbol version names like `GLIBC_2.17` or `GLIBCXX_3.4`
+fn highest_glibc<'a>(names: impl Iterator<Item = &'a str>) -> Option<String> {
+    names
+        .filter_map(|name| name.strip_prefix("GLIBC_"))
+        .filter(|version| version.starts_with(|c: char| c.is_ascii_digit()))
+        .max_by(|a, b| compare_versions(a, b))
+        .map(str::to_string)
+}
+
+/// Compare dotted version numbers like `2.34` and `2.4`
+fn compare_versions(a: &str, b: &str) -> std::cmp::Ordering {
+    let parse = |v: &str| v.split('.').map(|n| n.parse::<u32>().unwrap_or(0)).collect::<Vec<_>>();
+    parse(a).cmp(&parse(b))
+}
+
+/// Directories searched for the shared libraries of an executable, roughly like the dynamic
+/// loader does: its RPATH and RUNPATH, LD_LIBRARY_PATH, ld.so.conf and the default directories
+fn library_dirs(elf: &Elf, origin: Option<&Path>) -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+
+    for path in elf.rpaths.iter().chain(&elf.runpaths).flat_map(|p| p.split(':')) {
+        if path.contains("$ORIGIN") || path.contains("${ORIGIN}") {
+            if let Some(origin) = origin {
+                let origin = origin.to_string_lossy();
+                dirs.push(PathBuf::from(path.replace("${ORIGIN}", &origin).replace("$ORIGIN", &origin)));
+            }
+        } else if !path.is_empty() {
+            dirs.push(PathBuf::from(path));
+        }
+    }
+    // Libraries shipped next to the executable in its archive
+    dirs.extend(origin.map(Path::to_path_buf));
+
+    if let Some(paths) = std::env::var_os("LD_LIBRARY_PATH") {
+        dirs.extend(std::env::split_paths(&paths));
+    }
+
+    read_ld_so_conf(Path::new("/etc/ld.so.conf"), &mut dirs, 0);
+
+    let multiarch = match Platform::host().arch.as_str() {
+        "x86_64" => "x86_64-linux-gnu",
+        "arm64" => "aarch64-linux-gnu",
+        "armv7" | "armv6" => "arm-linux-gnueabihf",
+        "armel" => "arm-linux-gnueabi",
+        "riscv64" => "riscv64-linux-gnu",
+        "ppc64le" => "powerpc64le-linux-gnu",
+        "s390x" => "s390x-linux-gnu",
+        "loongarch64" => "loongarch64-linux-gnu",
+        _ => "i386-linux-gnu",
+    };
+    for dir in ["/lib", "/lib64", "/usr/lib", "/usr/lib64", "/usr/local/lib"] {
+        dirs.push(PathBuf::from(dir));
+        dirs.push(Path::new(dir).join(multiarch));
+    }
+
+    dirs
+}
+
+/// Add the directories listed in an ld.so.conf file, following its `include` lines
+fn read_ld_so_conf(path: &Path, dirs: &mut Vec<PathBuf>, depth: usize) {
+    let Ok(content) = fs::read_to_string(path) else {
+        return;
+    };
+    for line in content.lines().map(|l| l.split('#').next().unwrap_or("").trim()) {
+        if let Some(pattern) = line.strip_prefix("include ") {
+            if depth < 4 {
+                let pattern = path.parent().unwrap_or(Path::new("/")).join(pattern.trim());
+                for included in expand_conf_pattern(&pattern) {
+                    read_ld_so_conf(&included, dirs, depth + 1);
+                }
+            }
+        } else if line.starts_with('/') {
+            dirs.push(PathBuf::from(line));
+        }
+    }
+}
+
+/// Files matching an include pattern of ld.so.conf, which only uses `*` in file names,
+/// like `/etc/ld.so.conf.d/*.conf`
+fn expand_conf_pattern(path: &Path) -> Vec<PathBuf> {
+    let (Some(dir), Some(name)) = (path.parent(), path.file_name().and_then(|n| n.to_str())) else {
+        return Vec::new();
+    };
+    let Some((prefix, suffix)) = name.split_once('*') else {
+        return vec![path.to_path_buf()];
+    };
+
+    let mut files: Vec<PathBuf> = fs::read_dir(dir).into_iter()
+        .flatten()
+        .filter_map(|e| e.ok())
+        .filter(|e| {
+            let name = e.file_name().to_string_lossy().to_string();
+            name.starts_with(prefix) && name.ends_with(suffix)
+        })
+        .map(|e| e.path())
+        .collect();
+    files.sort();
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cmp::Ordering;
+
+    #[test]
+    fn compare_glibc_versions() {
+        assert_eq!(compare_versions("2.17", "2.28"), Ordering::Less);
+        assert_eq!(compare_versions("2.28", "2.17"), Ordering::Greater);
+        assert_eq!(compare_versions("2.9", "2.10"), Ordering::Less);
+        assert_eq!(compare_versions("2.36", "2.36"), Ordering::Equal);
+        assert_eq!(compare_versions("2.3", "2.3.4"), Ordering::Less);
+    }
+
+    #[test]
+    fn highest_required_glibc() {
+        let highest = |names: &[&'static str]| highest_glibc(names.iter().copied());
+        assert_eq!(highest(&["GLIBC_2.2.5", "GLIBC_2.28", "GLIBC_2.17"]).as_deref(), Some("2.28"));
+        assert_eq!(highest(&["GLIBC_2.9", "GLIBC_2.10"]).as_deref(), Some("2.10"));
+        // Not glibc itself, or not a version
+        assert_eq!(highest(&["GLIBCXX_3.4.29", "CXXABI_1.3", "GLIBC_PRIVATE"]), None);
+        assert_eq!(highest(&[]), None);
+    }
+
+    #[test]
+    #[cfg(all(target_os = "linux", target_env = "gnu"))]
+    fn executables_linked_with_glibc_require_it() {
+        let data = fs::read(std::env::current_exe().unwrap()).unwrap();
+        let required = required_glibc(&Elf::parse(&data).unwrap());
+        assert!(required.is_some_and(|v| compare_versions(&v, "2.2").is_ge()));
+    }
+}
@@ -0,0 +1,178 @@
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, RwLock};
+
+/// Something that happened while resolving, downloading or installing tools
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    /// Looking up the release of a tool
+    Resolving { tool: String, repo: String },
+    /// The release to install was found
+    Resolved { tool: String, version: String },
+    /// The asset to download was selected
+    AssetSelected { tool: String, asset: String, size: u64 },
+    /// A download started, `size` is 0 if unknown
+    DownloadStarted { url: String, size: u64 },
+    /// Bytes received so far for a download
+    DownloadProgress { url: String, downloaded: u64 },
+    /// A download completed, `source_url` is the URL that served it (a mirror or `url` itself)
+    DownloadFinished { url: String, source_url: String, size: u64 },
+    /// A download failed for good
+    DownloadFailed { url: String, error: String },
+    /// A failed request will be retried
+    Retry { url: String, attempt: usize, delay_secs: f64, error: String },
+    /// An archive is being extracted
+    Extracting { tool: String, asset: String },
+    /// The executable was found in the extracted archive
+    ExecutableFound { tool: String, path: PathBuf },
+    /// A tool was installed
+    Installed { tool: String, path: PathBuf },
+    /// Installing a tool failed
+    Failed { tool: String, error: String },
+    /// Something worth knowing that doesn't fit another event
+    Info { message: String },
+    /// Something went wrong, but the operation goes on
+    Warning { message: String },
+}
+
+/// Receives the events of coolclis operations, to display or record them
+pub trait Reporter: Send + Sync {
+    fn report(&self, event: &Event);
+}
+
+/// Human-readable output on the terminal, with progress bars for downloads
+#[derive(Default)]
+pub struct TerminalReporter {
+    multi: MultiProgress,
+    bars: Mutex<HashMap<String, ProgressBar>>,
+    stderr: bool,
+}
+
+impl TerminalReporter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A reporter printing its messages to stderr, leaving stdout to the output of another program
+    pub fn on_stderr() -> Self {
+        Self { stderr: true, ..Self::default() }
+    }
+
+    /// Print a line without garbling the progress bars
+    fn println(&self, line: &str) {
+        if self.stderr {
+            self.multi.suspend(|| eprintln!("{}", line));
+        } else {
+            self.multi.suspend(|| println!("{}", line));
+        }
+    }
+}
+
+impl Reporter for TerminalReporter {
+    fn report(&self, event: &Event) {
+        match event {
+            Event::Resolving { tool, repo } => self.println(&format!("Resolving {} from {}", tool, repo)),
+            Event::Resolved { tool, version } => self.println(&format!("Found {} release: {}", tool, version)),
+            Event::AssetSelected { tool, asset, size } => {
+                self.println(&format!("Selected {} asset: {} ({} bytes)", tool, asset, size))
+            }
+            Event::DownloadStarted { url, size } => {
+                // One bar per download, all shown together when installing several tools
+                let pb = self.multi.add(ProgressBar::new(*size));
+                pb.set_style(
+                    ProgressStyle::default_bar()
+                        .template("{spinner:.green} {msg:30!} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta})")
+                        .unwrap()
+                        .progress_chars("#>-"),
+                );
+                pb.set_message(url.rsplit('/').next().unwrap_or(url).to_string());
+                self.bars.lock().unwrap().insert(url.clone(), pb);
+            }
+            Event::DownloadProgress { url, downloaded } => {
+                if let Some(pb) = self.bars.lock().unwrap().get(url) {
+                    pb.set_position(*downloaded);
+                }
+            }
+            Event::DownloadFinished { url, source_url, .. } => {
+                if let Some(pb) = self.bars.lock().unwrap().remove(url) {
+                    pb.finish_with_message("Download complete");
+                }
+                if source_url != url {
+                    self.println(&format!("Downloaded from mirror: {}", source_url));
+                }
+            }
+            Event::DownloadFailed { url, .. } => {
+                if let Some(pb) = self.bars.lock().unwrap().remove(url) {
+                    pb.abandon_with_message("Download failed");
+                }
+            }
+            Event::Retry { attempt, delay_secs, error, .. } => {
+                self.println(&format!("Attempt {} failed: {}", attempt, error));
+                self.println(&format!("Retrying in {:.1} seconds...", delay_secs));
+            }
+            Event::Extracting { tool, .. } => self.println(&format!("Extracting {} archive...", tool)),
+            Event::ExecutableFound { path, .. } => self.println(&format!("Found executable: {}", path.display())),
+            Event::Installed { tool, path } => {
+                self.println(&format!("Successfully installed {} to {}", tool, path.display()))
+            }
+            Event::Failed { tool, error } => {
+                self.multi.suspend(|| eprintln!("Failed to install {}: {}", tool, error))
+            }
+            Event::Info { message } => self.println(message),
+            Event::Warning { message } => self.multi.suspend(|| eprintln!("Warning: {}", message)),
+        }
+    }
+}
+
+/// Reports nothing at all
+pub struct SilentReporter;
+
+impl Reporter for SilentReporter {
+    fn report(&self, _event: &Event) {}
+}
+
+/// Writes every event as a line of JSON to stdout, for other programs to consume
+pub struct JsonReporter;
+
+impl Reporter for JsonReporter {
+    fn report(&self, event: &Event) {
+        if let Ok(line) = serde_json::to_string(event) {
+            let mut stdout = std::io::stdout().lock();
+            let _ = writeln!(stdout, "{}", line);
+        }
+    }
+}
+
+static REPORTER: RwLock<Option<Arc<dyn Reporter>>> = RwLock::new(None);
+
+/// Replace the reporter receiving the events of this process
+pub fn set_reporter(reporter: Arc<dyn Reporter>) {
+    *REPORTER.write().unwrap() = Some(reporter);
+}
+
+/// Get the reporter of this process, a `TerminalReporter` unless replaced with `set_reporter`
+pub fn reporter() -> Arc<dyn Reporter> {
+    if let Some(reporter) = REPORTER.read().unwrap().as_ref() {
+        return reporter.clone();
+    }
+    REPORTER.write().unwrap()
+        .get_or_insert_with(|| Arc::new(TerminalReporter::new()))
+        .clone()
+}
+
+/// Send an event to the reporter of this process
+pub fn report(event: Event) {
+    reporter().report(&event);
+}
+
+pub fn info(message: impl Into<String>) {
+    report(Event::Info { message: message.into() });
+}
+
+pub fn warning(message: impl Into<String>) {
+    report(Event::Warning { message: message.into() });
+}
@@ -0,0 +1,313 @@
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::{Response, StatusCode};
+use std::fmt;
+use std::time::{Duration, SystemTime};
+
+use crate::scheduler::rate_limit_delay;
+
+/// How many times and how long apart a failed request is retried
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Total number of attempts, including the first one
+    pub max_attempts: usize,
+    /// Delay before the first retry, doubled on every following one
+    pub base_delay: Duration,
+    /// Upper bound for the delay, including one requested by the server
+    pub max_delay: Duration,
+    /// Fraction of the delay that is randomized, from 0.0 (none) to 1.0
+    pub jitter: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 4,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(30),
+            jitter: 0.5,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay before the attempt following `attempt` (1-based).
+    /// A delay requested by the server through `Retry-After` takes precedence, up to `max_delay`.
+    pub fn delay(&self, attempt: usize, retry_after: Option<Duration>) -> Duration {
+        if let Some(retry_after) = retry_after {
+            return retry_after.min(self.max_delay);
+        }
+
+        let exponent = attempt.saturating_sub(1).min(16) as u32;
+        let delay = self.base_delay.saturating_mul(1 << exponent).min(self.max_delay);
+
+        // Randomize the delay so concurrent retries don't hit the server at once
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        delay.mul_f64(1.0 - jitter * fastrand::f64())
+    }
+}
+
+/// Why a single attempt failed
+#[derive(Debug)]
+pub enum Failure {
+    /// The server answered with a non-success status
+    Status {
+        status: StatusCode,
+        retry_after: Option<Duration>,
+        rate_limited: bool,
+    },
+    /// The request could not be sent or the response could not be read
+    Transport(reqwest::Error),
+    /// No data was received for longer than the read timeout
+    Stalled,
+    /// The response body was not what we expected
+    Decode(String),
+    /// A `file://` URL could not be read
+    File(std::io::Error),
+}
+
+impl Failure {
+    pub fn from_response(response: &Response) -> Self {
+        let rate_limit = rate_limit_delay(response);
+        Failure::Status {
+            status: response.status(),
+            retry_after: rate_limit.or_else(|| parse_retry_after(response.headers())),
+            rate_limited: rate_limit.is_some(),
+        }
+    }
+
+    /// Wrap a transport error. The URL is dropped, as it's already part of `RequestError`.
+    pub fn from_transport_error(error: reqwest::Error) -> Self {
+        Failure::Transport(error.without_url())
+    }
+
+    /// Classify a transport error that happened while reading the body
+    pub fn from_body_error(error: reqwest::Error) -> Self {
+        if error.is_timeout() {
+            Failure::Stalled
+        } else {
+            Failure::from_transport_error(error)
+        }
+    }
+
+    /// Whether trying again may succeed
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Failure::Status { status, rate_limited, .. } => {
+                *rate_limited
+                    || matches!(
+                        *status,
+                        StatusCode::REQUEST_TIMEOUT
+                            | StatusCode::TOO_MANY_REQUESTS
+                            | StatusCode::INTERNAL_SERVER_ERROR
+                            | StatusCode::BAD_GATEWAY
+                            | StatusCode::SERVICE_UNAVAILABLE
+                            | StatusCode::GATEWAY_TIMEOUT
+                    )
+            }
+            Failure::Transport(e) => !(e.is_builder() || e.is_redirect() || e.is_decode()),
+            Failure::Stalled => true,
+            Failure::Decode(_) | Failure::File(_) => false,
+        }
+    }
+
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Failure::Status { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            Failure::Status { status, .. } => Some(*status),
+            // A missing file in a local mirror is the equivalent of a 404
+            Failure::File(e) if e.kind() == std::io::ErrorKind::NotFound => Some(StatusCode::NOT_FOUND),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Failure::Status { status, rate_limited: true, .. } => write!(f, "HTTP {} (rate limited)", status),
+            Failure::Status { status, .. } => write!(f, "HTTP {}", status),
+            Failure::Transport(e) => {
+                // reqwest errors only describe the step that failed, the cause is in the source chain
+                let mut message = e.to_string();
+                let mut source = std::error::Error::source(e);
+                while let Some(cause) = source {
+                    let cause_message = cause.to_string();
+                    if !message.ends_with(&cause_message) {
+                        message = format!("{}: {}", message, cause_message);
+                    }
+                    source = cause.source();
+                }
+                write!(f, "{}", message)
+            }
+            Failure::Stalled => write!(f, "transfer stalled, no data received within the read timeout"),
+            Failure::Decode(e) => write!(f, "invalid response: {}", e),
+            Failure::File(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+/// Record of one failed attempt
+#[derive(Debug)]
+pub struct AttemptRecord {
+    pub failure: Failure,
+    /// Delay waited before the next attempt, if there was one
+    pub delay: Option<Duration>,
+}
+
+/// Error returned when a request failed for good, with the history of all attempts
+#[derive(Debug)]
+pub struct RequestError {
+    pub url: String,
+    pub attempts: Vec<AttemptRecord>,
+}
+
+impl RequestError {
+    pub fn last_failure(&self) -> Option<&Failure> {
+        self.attempts.last().map(|a| &a.failure)
+    }
+
+    /// HTTP status of the last attempt, if the server answered
+    pub fn status(&self) -> Option<StatusCode> {
+        self.last_failure().and_then(Failure::status)
+    }
+}
+
+impl fmt::Display for RequestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some(last) = self.last_failure() else {
+            return write!(f, "{}: request failed", self.url);
+        };
+        write!(f, "{}: {}", self.url, last)?;
+
+        let delays: Vec<String> = self.attempts.iter()
+            .filter_map(|a| a.delay.map(|d| format!("{:.1}s", d.as_secs_f64())))
+            .collect();
+        if !delays.is_empty() {
+            write!(f, " (gave up after {} attempts, retried after {})", self.attempts.len(), delays.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for RequestError {}
+
+/// Parse a `Retry-After` header, given either in seconds or as an HTTP date
+pub fn parse_retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let date = httpdate::parse_http_date(value).ok()?;
+    Some(date.duration_since(SystemTime::now()).unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy { jitter: 0.0, ..RetryPolicy::default() }
+    }
+
+    fn response(status: u16, headers: &[(&str, &str)]) -> Response {
+        let mut builder = hyper::Response::builder().status(status);
+        for (name, value) in headers {
+            builder = builder.header(*name, *value);
+        }
+        Response::from(builder.body("").unwrap())
+    }
+
+    fn headers(retry_after: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_str(retry_after).unwrap());
+        headers
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_max_delay() {
+        let delays: Vec<u64> = (1..=7).map(|attempt| policy().delay(attempt, None).as_secs()).collect();
+        assert_eq!(delays, [1, 2, 4, 8, 16, 30, 30]);
+        assert_eq!(policy().delay(usize::MAX, None), Duration::from_secs(30));
+    }
+
+    #[test]
+    fn jitter_only_shortens_the_delay() {
+        let policy = RetryPolicy { jitter: 1.0, ..RetryPolicy::default() };
+        for _ in 0..100 {
+            assert!(policy.delay(3, None) <= Duration::from_secs(4));
+        }
+    }
+
+    #[test]
+    fn retry_after_takes_precedence_up_to_max_delay() {
+        assert_eq!(policy().delay(1, Some(Duration::from_secs(7))), Duration::from_secs(7));
+        assert_eq!(policy().delay(5, Some(Duration::from_secs(2))), Duration::from_secs(2));
+        assert_eq!(policy().delay(1, Some(Duration::from_secs(86400))), Duration::from_secs(30));
+    }
+
+    #[test]
+    fn retry_after_in_seconds() {
+        assert_eq!(parse_retry_after(&headers("120")), Some(Duration::from_secs(120)));
+        assert_eq!(parse_retry_after(&headers(" 0 ")), Some(Duration::ZERO));
+        assert_eq!(parse_retry_after(&headers("soon")), None);
+        assert_eq!(parse_retry_after(&headers("-5")), None);
+        assert_eq!(parse_retry_after(&HeaderMap::new()), None);
+    }
+
+    #[test]
+    fn retry_after_as_http_date() {
+        let later = SystemTime::now() + Duration::from_secs(90);
+        let delay = parse_retry_after(&headers(&httpdate::fmt_http_date(later))).unwrap();
+        assert!(delay > Duration::from_secs(85) && delay <= Duration::from_secs(90), "{:?}", delay);
+
+        // A date in the past means retrying right away
+        assert_eq!(parse_retry_after(&headers("Wed, 21 Oct 2015 07:28:00 GMT")), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn retryable_statuses() {
+        for status in [408, 429, 500, 502, 503, 504] {
+            assert!(Failure::from_response(&response(status, &[])).is_retryable(), "{}", status);
+        }
+        for status in [400, 401, 403, 404, 410, 422, 501] {
+            assert!(!Failure::from_response(&response(status, &[])).is_retryable(), "{}", status);
+        }
+    }
+
+    /// GitHub answers 403 instead of 429 when the rate limit is exhausted
+    #[test]
+    fn rate_limited_403_is_retryable() {
+        let failure = Failure::from_response(&response(403, &[("x-ratelimit-remaining", "0"), ("retry-after", "12")]));
+        assert!(failure.is_retryable());
+        assert_eq!(failure.retry_after(), Some(Duration::from_secs(12)));
+        assert_eq!(failure.to_string(), "HTTP 403 Forbidden (rate limited)");
+
+        let forbidden = Failure::from_response(&response(403, &[("x-ratelimit-remaining", "10")]));
+        assert!(!forbidden.is_retryable());
+        assert_eq!(forbidden.retry_after(), None);
+    }
+
+    #[test]
+    fn retry_after_of_a_503() {
+        let failure = Failure::from_response(&response(503, &[("retry-after", "5")]));
+        assert!(failure.is_retryable());
+        assert_eq!(failure.retry_after(), Some(Duration::from_secs(5)));
+        assert_eq!(failure.status(), Some(StatusCode::SERVICE_UNAVAILABLE));
+    }
+
+    #[test]
+    fn local_failures() {
+        assert!(Failure::Stalled.is_retryable());
+        assert!(!Failure::Decode("bad json".to_string()).is_retryable());
+        let missing = Failure::File(std::io::Error::from(std::io::ErrorKind::NotFound));
+        assert!(!missing.is_retryable());
+        assert_eq!(missing.status(), Some(StatusCode::NOT_FOUND));
+    }
+}
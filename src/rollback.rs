@@ -0,0 +1,140 @@
+use anyhow::{anyhow, Result};
+use std::fs;
+
+use crate::report::info;
+use crate::state::{format_time, load_state, update_state, InstallState};
+use crate::store::{activate, tool_store_dir, version_dir};
+
+/// Reactivate the version of `tool` that was active before the current one.
+/// The version rolled back from is removed from the store.
+pub fn rollback(tool: &str) -> Result<()> {
+    let (from, to) = update_state(|state| {
+        let tool_state = state.tools.get_mut(tool)
+            .ok_or_else(|| anyhow!("{} was not installed by coolclis", tool))?;
+        let to = tool_state.history.pop()
+            .ok_or_else(|| anyhow!("No previous version of {} to roll back to", tool))?;
+        let from = tool_state.active.clone();
+        activate(state, tool, &to, false)?;
+        Ok((from, to))
+    })?;
+
+    info(format!("Rolled back {} from {} to {}", tool, from, to));
+    Ok(())
+}
+
+/// Undo the install of `version`, the active version of `tool`: reactivate the previous version,
+/// or another version in the store if there is none, or remove the tool if it has no other
+/// version. Returns the version now active, if any.
+pub fn undo_install(tool: &str, version: &str) -> Result<Option<String>> {
+    update_state(|state| undo(state, tool, version))
+}
+
+fn undo(state: &mut InstallState, tool: &str, version: &str) -> Result<Option<String>> {
+    let Some(tool_state) = state.tools.get_mut(tool) else {
+        return Ok(None);
+    };
+    if tool_state.active != version {
+        return Ok(Some(tool_state.active.clone()));
+    }
+
+    match tool_state.history.pop() {
+        Some(previous) => {
+            activate(state, tool, &previous, false)?;
+            Ok(Some(previous))
+        }
+        None => {
+            // Only the failed version goes, versions pinned by projects stay in the store
+            tool_state.versions.remove(version);
+            let _ = fs::remove_dir_all(version_dir(tool, version)?);
+            if let Some(remaining) = tool_state.versions.keys().next_back().cloned() {
+                activate(state, tool, &remaining, false)?;
+                return Ok(Some(remaining));
+            }
+
+            let _ = fs::remove_file(&tool_state.link);
+            let _ = fs::remove_dir(tool_store_dir(tool)?);
+            state.tools.remove(tool);
+            Ok(None)
+        }
+    }
+}
+
+/// Print the active version of `tool` and the previously active ones kept for rollback
+pub fn list_generations(tool: &str) -> Result<()> {
+    let state = load_state()?;
+    let tool_state = state.tools.get(tool)
+        .ok_or_else(|| anyhow!("{} was not installed by coolclis", tool))?;
+
+    let installed = |version: &str| match tool_state.versions.get(version).map(|v| v.installed_at) {
+        None | Some(0) => "-".to_string(),
+        Some(secs) => format_time(secs),
+    };
+
+    println!("{:<10} {:<15} INSTALLED", "GENERATION", "VERSION");
+    println!("{:<10} {:<15} ---------", "----------", "-------");
+    println!("{:<10} {:<15} {}", "current", tool_state.active, installed(&tool_state.active));
+    for (i, version) in tool_state.history.iter().rev().enumerate() {
+        println!("{:<10} {:<15} {}", i + 1, version, installed(version));
+    }
+    if tool_state.history.is_empty() {
+        println!("No previous versions of {} kept", tool);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{StoredVersion, ToolState};
+    use crate::store::add_version;
+    use std::collections::BTreeMap;
+    use std::path::{Path, PathBuf};
+
+    const TOOL: &str = "coolclis-undo-test";
+
+    fn stored() -> StoredVersion {
+        StoredVersion {
+            installed_at: 1,
+            executable: PathBuf::from(TOOL),
+            files: BTreeMap::new(),
+            project: false,
+            asset: None,
+        }
+    }
+
+    /// A tool with `v1.0.0` active. As a shim, activating a version doesn't touch its bin entry.
+    fn state() -> InstallState {
+        let tool_state = ToolState {
+            repo: "x/tool".to_string(),
+            active: "v1.0.0".to_string(),
+            link: PathBuf::from("/nonexistent/bin").join(TOOL),
+            versions: BTreeMap::from([("v1.0.0".to_string(), stored())]),
+            history: Vec::new(),
+            shim: true,
+        };
+        InstallState { tools: BTreeMap::from([(TOOL.to_string(), tool_state)]) }
+    }
+
+    /// Whatever `keep_generations` is, a failed install goes back to the version before it
+    #[test]
+    fn undo_install_restores_the_previous_version() {
+        let mut state = state();
+        add_version(&mut state, TOOL, "x/tool", "v2.0.0", stored(), Path::new("/nonexistent/bin/tool")).unwrap();
+        let tool_state = &state.tools[TOOL];
+        assert_eq!(tool_state.active, "v2.0.0");
+        assert!(tool_state.versions.contains_key("v1.0.0"));
+
+        assert_eq!(undo(&mut state, TOOL, "v2.0.0").unwrap().as_deref(), Some("v1.0.0"));
+        let tool_state = &state.tools[TOOL];
+        assert_eq!(tool_state.active, "v1.0.0");
+        assert_eq!(tool_state.versions.keys().collect::<Vec<_>>(), ["v1.0.0"]);
+    }
+
+    #[test]
+    fn undo_the_only_version_removes_the_tool() {
+        let mut state = state();
+        assert_eq!(undo(&mut state, TOOL, "v1.0.0").unwrap(), None);
+        assert!(!state.tools.contains_key(TOOL));
+    }
+}
@@ -0,0 +1,328 @@
+use anyhow::{anyhow, Context, Result};
+use reqwest::{Certificate, Identity, NoProxy, Proxy, Response, StatusCode};
+use std::collections::HashMap;
+use std::fs;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::report::warning;
+use crate::retry::parse_retry_after;
+use crate::settings::{settings, Settings};
+
+/// How long to pause all requests after a rate-limit response without a usable hint
+const DEFAULT_BACKOFF_SECS: u64 = 30;
+
+/// Upper bound for a single global pause, whatever the server asks for
+const MAX_BACKOFF_SECS: u64 = 300;
+
+/// Shared HTTP client with per-host concurrency limits, a global backoff and
+/// an optional bandwidth limit.
+///
+/// Every network request goes through `acquire` before it is sent, and reports
+/// its response to `observe`, so that a rate-limit response seen by one request
+/// pauses all the others. Downloads pass each chunk they receive to `throttle`.
+pub struct Scheduler {
+    client: reqwest::Client,
+    max_per_host: usize,
+    hosts: Mutex<HashMap<String, Arc<Semaphore>>>,
+    paused_until: Mutex<Option<Instant>>,
+    bandwidth: Option<TokenBucket>,
+}
+
+/// Token bucket shared by all downloads, refilled at `rate` bytes per second
+struct TokenBucket {
+    rate: f64,
+    state: Mutex<(f64, Instant)>,
+}
+
+static SCHEDULER: OnceLock<Scheduler> = OnceLock::new();
+
+/// Create the scheduler of this process from the settings, with `limit_rate` overriding
+/// the bandwidth limit of the settings if given.
+/// This reports invalid proxy, certificate or rate settings, so call it before any request is made.
+pub fn init_scheduler(limit_rate: Option<&str>) -> Result<&'static Scheduler> {
+    if let Some(scheduler) = SCHEDULER.get() {
+        return Ok(scheduler);
+    }
+    let scheduler = Scheduler::new(settings(), limit_rate)?;
+    Ok(SCHEDULER.get_or_init(|| scheduler))
+}
+
+/// Get the scheduler of this process, created once on first use
+pub fn scheduler() -> &'static Scheduler {
+    SCHEDULER.get_or_init(|| Scheduler::new(settings(), None).expect("Failed to build HTTP client"))
+}
+
+impl Scheduler {
+    pub fn new(settings: &Settings, limit_rate: Option<&str>) -> Result<Self> {
+        let limit_rate = match limit_rate.or(settings.limit_rate.as_deref()) {
+            Some(rate) => parse_rate(rate)?,
+            None => 0,
+        };
+
+        Ok(Self {
+            client: build_client(settings)?,
+            max_per_host: settings.max_connections_per_host.max(1),
+            hosts: Mutex::new(HashMap::new()),
+            paused_until: Mutex::new(None),
+            bandwidth: (limit_rate > 0).then(|| TokenBucket::new(limit_rate)),
+        })
+    }
+
+    pub fn client(&self) -> &reqwest::Client {
+        &self.client
+    }
+
+    /// Wait for any global backoff to end and for a free slot on the host of `url`.
+    /// The slot is held until the returned permit is dropped.
+    pub async fn acquire(&self, url: &str) -> OwnedSemaphorePermit {
+        let semaphore = self.host_semaphore(url);
+
+        loop {
+            let paused_until = *self.paused_until.lock().unwrap();
+            match paused_until {
+                Some(until) if until > Instant::now() => tokio::time::sleep_until(until.into()).await,
+                _ => break,
+            }
+        }
+
+        semaphore.acquire_owned().await.expect("Host semaphore closed")
+    }
+
+    /// Pause all requests if the response tells us we're being rate limited
+    pub fn observe(&self, response: &Response) {
+        if let Some(delay) = rate_limit_delay(response) {
+            let until = Instant::now() + delay;
+            let mut paused_until = self.paused_until.lock().unwrap();
+            if paused_until.is_none_or(|current| current < until) {
+                warning(format!("Rate limited by {}, pausing requests for {} seconds",
+                    response.url().host_str().unwrap_or("server"), delay.as_secs()));
+                *paused_until = Some(until);
+            }
+        }
+    }
+
+    /// Account for `bytes` received by a download, waiting as long as needed to stay
+    /// under the bandwidth limit
+    pub async fn throttle(&self, bytes: usize) {
+        if let Some(bandwidth) = &self.bandwidth {
+            let wait = bandwidth.take(bytes as f64);
+            if !wait.is_zero() {
+                tokio::time::sleep(wait).await;
+            }
+        }
+    }
+
+    fn host_semaphore(&self, url: &str) -> Arc<Semaphore> {
+        let host = reqwest::Url::parse(url)
+            .ok()
+            .and_then(|u| u.host_str().map(str::to_string))
+            .unwrap_or_default();
+
+        self.hosts
+            .lock()
+            .unwrap()
+            .entry(host)
+            .or_insert_with(|| Arc::new(Semaphore::new(self.max_per_host)))
+            .clone()
+    }
+}
+
+impl TokenBucket {
+    fn new(rate: u64) -> Self {
+        let rate = rate as f64;
+        Self {
+            rate,
+            state: Mutex::new((rate, Instant::now())),
+        }
+    }
+
+    /// Take `amount` tokens and return how long to wait until they were available.
+    /// The bucket goes into debt, so concurrent takers queue up behind each other.
+    fn take(&self, amount: f64) -> Duration {
+        let mut state = self.state.lock().unwrap();
+        let (tokens, last) = &mut *state;
+        let now = Instant::now();
+
+        // Allow bursts of at most one second worth of data
+        *tokens = (*tokens + now.duration_since(*last).as_secs_f64() * self.rate).min(self.rate);
+        *last = now;
+        *tokens -= amount;
+
+        if *tokens < 0.0 {
+            Duration::from_secs_f64(-*tokens / self.rate)
+        } else {
+            Duration::ZERO
+        }
+    }
+}
+
+/// Parse a rate in bytes per second like `500K` or `2M`, with binary multiples.
+/// 0 means unlimited.
+pub fn parse_rate(rate: &str) -> Result<u64> {
+    let trimmed = rate.trim();
+    let (number, multiplier) = match trimmed.char_indices().last() {
+        Some((i, 'k' | 'K')) => (&trimmed[..i], 1024.0),
+        Some((i, 'm' | 'M')) => (&trimmed[..i], 1024.0 * 1024.0),
+        Some((i, 'g' | 'G')) => (&trimmed[..i], 1024.0 * 1024.0 * 1024.0),
+        _ => (trimmed, 1.0),
+    };
+
+    match number.trim().parse::<f64>() {
+        Ok(value) if value.is_finite() && value >= 0.0 => Ok((value * multiplier) as u64),
+        _ => Err(anyhow!("Invalid rate '{}', expected bytes per second like 500K or 2M", rate)),
+    }
+}
+
+/// Build the HTTP client shared by all requests, with the proxy and TLS settings applied
+fn build_client(settings: &Settings) -> Result<reqwest::Client> {
+    // No total timeout: large downloads may take long, as long as data keeps flowing
+    let mut builder = reqwest::Client::builder()
+        .connect_timeout(Duration::from_secs(settings.connect_timeout_secs))
+        .read_timeout(Duration::from_secs(settings.read_timeout_secs));
+
+    // Without a proxy in the settings, reqwest uses the proxy environment variables
+    if let Some(proxy_url) = &settings.proxy {
+        let no_proxy = match &settings.no_proxy {
+            Some(hosts) => NoProxy::from_string(hosts),
+            None => NoProxy::from_env(),
+        };
+        let proxy = Proxy::all(proxy_url)
+            .map_err(|e| anyhow!("Invalid proxy {}: {}", proxy_url, e))?
+            .no_proxy(no_proxy);
+        builder = builder.proxy(proxy);
+    }
+
+    if let Some(ca_path) = &settings.ca_certificates {
+        let pem = fs::read(ca_path)
+            .with_context(|| format!("Failed to read CA certificates from {}", ca_path.display()))?;
+        let certificates = Certificate::from_pem_bundle(&pem)
+            .map_err(|e| anyhow!("Invalid CA certificates in {}: {}", ca_path.display(), e))?;
+        for certificate in certificates {
+            builder = builder.add_root_certificate(certificate);
+        }
+    }
+
+    if let Some(cert_path) = &settings.client_certificate {
+        let key_path = settings.client_key.as_ref().unwrap_or(cert_path);
+        let cert = fs::read(cert_path)
+            .with_context(|| format!("Failed to read client certificate from {}", cert_path.display()))?;
+        let key = fs::read(key_path)
+            .with_context(|| format!("Failed to read client key from {}", key_path.display()))?;
+        let identity = Identity::from_pkcs8_pem(&cert, &key)
+            .map_err(|e| anyhow!("Invalid client certificate {}: {}", cert_path.display(), e))?;
+        builder = builder.identity(identity);
+    }
+
+    builder.build().map_err(|e| anyhow!("Failed to build HTTP client: {}", e))
+}
+
+/// How long to back off for, if the response is a rate-limit response
+pub fn rate_limit_delay(response: &Response) -> Option<Duration> {
+    let status = response.status();
+    let headers = response.headers();
+    let header_u64 = |name: &str| {
+        headers.get(name)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse::<u64>().ok())
+    };
+
+    // GitHub answers 403 instead of 429 when the primary rate limit is exhausted
+    let exhausted = header_u64("x-ratelimit-remaining") == Some(0);
+    if status != StatusCode::TOO_MANY_REQUESTS && !(status == StatusCode::FORBIDDEN && exhausted) {
+        return None;
+    }
+
+    let secs = if let Some(retry_after) = parse_retry_after(headers) {
+        retry_after.as_secs()
+    } else if let (true, Some(reset)) = (exhausted, header_u64("x-ratelimit-reset")) {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        reset.saturating_sub(now)
+    } else {
+        DEFAULT_BACKOFF_SECS
+    };
+
+    Some(Duration::from_secs(secs.clamp(1, MAX_BACKOFF_SECS)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rates_with_units() {
+        for (rate, expected) in [
+            ("500", 500),
+            ("500K", 500 * 1024),
+            ("500k", 500 * 1024),
+            ("2M", 2 * 1024 * 1024),
+            ("1.5M", 3 * 512 * 1024),
+            ("1G", 1024 * 1024 * 1024),
+            (" 2 M ", 2 * 1024 * 1024),
+        ] {
+            assert_eq!(parse_rate(rate).unwrap(), expected, "{}", rate);
+        }
+    }
+
+    fn response(status: u16, headers: &[(&str, String)]) -> Response {
+        let mut builder = hyper::Response::builder().status(status);
+        for (name, value) in headers {
+            builder = builder.header(*name, value);
+        }
+        Response::from(builder.body("").unwrap())
+    }
+
+    fn now_secs() -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+    }
+
+    #[test]
+    fn rate_limit_hints() {
+        let retry_after = response(429, &[("retry-after", "42".to_string())]);
+        assert_eq!(rate_limit_delay(&retry_after), Some(Duration::from_secs(42)));
+
+        let reset = response(403, &[
+            ("x-ratelimit-remaining", "0".to_string()),
+            ("x-ratelimit-reset", (now_secs() + 60).to_string()),
+        ]);
+        let delay = rate_limit_delay(&reset).unwrap();
+        assert!(delay > Duration::from_secs(55) && delay <= Duration::from_secs(60), "{:?}", delay);
+
+        let no_hint = response(429, &[]);
+        assert_eq!(rate_limit_delay(&no_hint), Some(Duration::from_secs(DEFAULT_BACKOFF_SECS)));
+    }
+
+    #[test]
+    fn rate_limit_delays_are_bounded() {
+        let far = response(429, &[("retry-after", "86400".to_string())]);
+        assert_eq!(rate_limit_delay(&far), Some(Duration::from_secs(MAX_BACKOFF_SECS)));
+        let past_reset = response(403, &[
+            ("x-ratelimit-remaining", "0".to_string()),
+            ("x-ratelimit-reset", (now_secs() - 60).to_string()),
+        ]);
+        assert_eq!(rate_limit_delay(&past_reset), Some(Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn other_responses_are_not_rate_limits() {
+        assert_eq!(rate_limit_delay(&response(403, &[])), None);
+        assert_eq!(rate_limit_delay(&response(403, &[("x-ratelimit-remaining", "5".to_string())])), None);
+        assert_eq!(rate_limit_delay(&response(503, &[("retry-after", "5".to_string())])), None);
+        assert_eq!(rate_limit_delay(&response(200, &[("x-ratelimit-remaining", "0".to_string())])), None);
+    }
+
+    /// A rate of zero means no limit
+    #[test]
+    fn zero_rate() {
+        assert_eq!(parse_rate("0").unwrap(), 0);
+        assert_eq!(parse_rate("0K").unwrap(), 0);
+    }
+
+    #[test]
+    fn invalid_rates() {
+        for rate in ["", "K", "fast", "-1M", "2T", "1e999", "NaN", "inf"] {
+            assert!(parse_rate(rate).is_err(), "{}", rate);
+        }
+    }
+}
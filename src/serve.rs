@@ -0,0 +1,218 @@
+use anyhow::Result;
+use http_body_util::Full;
+use hyper::body::{Bytes, Incoming};
+use hyper::header::{CONTENT_TYPE, HOST};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use percent_encoding::percent_decode_str;
+use serde_json::json;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::TcpListener;
+
+use crate::cache::{read_cached_download, read_cached_release, write_cached_download, write_cached_release};
+use crate::downloader::Downloader;
+use crate::github::{get_latest_release, get_releases_page, get_specific_release, Release};
+use crate::report::warning;
+use crate::retry::RequestError;
+
+struct ServeState {
+    /// How long a cached `latest` release is served before asking upstream again
+    latest_ttl: Duration,
+    /// One lock per download URL, so concurrent requests for an asset fetch it only once
+    downloads: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
+}
+
+/// Serve a GitHub-compatible subset of the API from the local caches, filling misses from upstream:
+///
+/// - `GET /repos/{owner}/{repo}/releases?page={page}`, listed upstream on every request
+/// - `GET /repos/{owner}/{repo}/releases/latest`
+/// - `GET /repos/{owner}/{repo}/releases/tags/{tag}`
+/// - `GET /download/{owner}/{repo}/{tag}/{asset}`, the `browser_download_url` of the served releases
+pub async fn serve(addr: SocketAddr, latest_ttl: Duration) -> Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    let state = Arc::new(ServeState {
+        latest_ttl,
+        downloads: Mutex::new(HashMap::new()),
+    });
+
+    println!("Serving release metadata and assets on http://{}", listener.local_addr()?);
+    println!("Point clients at it with a mirror rule for https://api.github.com/");
+
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let state = state.clone();
+        tokio::spawn(async move {
+            let service = service_fn(move |request| handle(request, state.clone()));
+            if let Err(e) = http1::Builder::new().serve_connection(TokioIo::new(stream), service).await {
+                eprintln!("Connection from {} failed: {}", peer, e);
+            }
+        });
+    }
+}
+
+async fn handle(request: Request<Incoming>, state: Arc<ServeState>) -> Result<Response<Full<Bytes>>, Infallible> {
+    if request.method() != Method::GET && request.method() != Method::HEAD {
+        return Ok(error_response(StatusCode::METHOD_NOT_ALLOWED, "Method Not Allowed"));
+    }
+
+    // Download URLs are built from the address the client used to reach us
+    let base_url = match request.headers().get(HOST).and_then(|h| h.to_str().ok()) {
+        Some(host) => format!("http://{}", host),
+        None => return Ok(error_response(StatusCode::BAD_REQUEST, "Missing Host header")),
+    };
+
+    let segments: Vec<String> = request.uri().path()
+        .trim_matches('/')
+        .split('/')
+        .map(|s| percent_decode_str(s).decode_utf8_lossy().to_string())
+        .collect();
+    let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+
+    let response = match segments.as_slice() {
+        ["repos", owner, repo, "releases"] => {
+            let page = request.uri().query().unwrap_or_default()
+                .split('&')
+                .find_map(|param| param.strip_prefix("page="))
+                .and_then(|page| page.parse().ok())
+                .unwrap_or(1);
+            releases_response(&format!("{}/{}", owner, repo), page, &base_url).await
+        }
+        ["repos", owner, repo, "releases", "latest"] => {
+            release_response(&format!("{}/{}", owner, repo), None, &base_url, &state).await
+        }
+        ["repos", owner, repo, "releases", "tags", tag] => {
+            release_response(&format!("{}/{}", owner, repo), Some(tag), &base_url, &state).await
+        }
+        ["download", owner, repo, tag, asset] => {
+            download_response(&format!("{}/{}", owner, repo), tag, asset, &state).await
+        }
+        _ => error_response(StatusCode::NOT_FOUND, "Not Found"),
+    };
+
+    println!("{} {} {}", request.method(), request.uri().path(), response.status().as_u16());
+    Ok(response)
+}
+
+async fn release_response(repo: &str, tag: Option<&str>, base_url: &str, state: &ServeState) -> Response<Full<Bytes>> {
+    let release = match get_release(repo, tag, state).await {
+        Ok(release) => release,
+        Err(e) => return upstream_error_response(&e),
+    };
+
+    Response::builder()
+        .header(CONTENT_TYPE, "application/json")
+        .body(Full::new(Bytes::from(served_release(&release, repo, base_url).to_string())))
+        .unwrap()
+}
+
+async fn releases_response(repo: &str, page: usize, base_url: &str) -> Response<Full<Bytes>> {
+    let releases = match get_releases_page(repo, page).await {
+        Ok(releases) => releases,
+        Err(e) => return upstream_error_response(&e),
+    };
+
+    // Cached so that their assets can be downloaded through us
+    for release in &releases {
+        if let Err(e) = write_cached_release(repo, Some(&release.tag_name), release) {
+            warning(format!("Failed to cache release of {}: {}", repo, e));
+        }
+    }
+    let body: Vec<serde_json::Value> = releases.iter()
+        .map(|release| served_release(release, repo, base_url))
+        .collect();
+
+    Response::builder()
+        .header(CONTENT_TYPE, "application/json")
+        .body(Full::new(Bytes::from(json!(body).to_string())))
+        .unwrap()
+}
+
+/// A release as sent to clients, which are sent to us for the assets as well
+fn served_release(release: &Release, repo: &str, base_url: &str) -> serde_json::Value {
+    let mut body = json!(release);
+    if let Some(assets) = body["assets"].as_array_mut() {
+        for asset in assets {
+            let name = asset["name"].as_str().unwrap_or_default().to_string();
+            asset["browser_download_url"] = json!(format!("{}/download/{}/{}/{}", base_url, repo, release.tag_name, name));
+        }
+    }
+    body
+}
+
+/// Get a release from the cache, or from upstream on a miss
+async fn get_release(repo: &str, tag: Option<&str>, state: &ServeState) -> Result<Release> {
+    // A tagged release rarely changes, the latest one does
+    let max_age = tag.is_none().then_some(state.latest_ttl);
+    if let Some(release) = read_cached_release(repo, tag, max_age) {
+        return Ok(release);
+    }
+
+    let release = match tag {
+        Some(tag) => get_specific_release(repo, tag).await?,
+        None => get_latest_release(repo).await?,
+    };
+
+    if let Err(e) = write_cached_release(repo, tag, &release) {
+        warning(format!("Failed to cache release of {}: {}", repo, e));
+    }
+    if tag.is_none() {
+        let _ = write_cached_release(repo, Some(&release.tag_name), &release);
+    }
+    Ok(release)
+}
+
+async fn download_response(repo: &str, tag: &str, asset: &str, state: &ServeState) -> Response<Full<Bytes>> {
+    // Prefer the upstream URL recorded in the release, which may differ from the usual layout
+    let url = read_cached_release(repo, Some(tag), None)
+        .and_then(|release| release.assets.into_iter().find(|a| a.name == asset))
+        .map(|a| a.browser_download_url)
+        .unwrap_or_else(|| format!("https://github.com/{}/releases/download/{}/{}", repo, tag, asset));
+
+    let lock = state.downloads.lock().unwrap()
+        .entry(url.clone())
+        .or_default()
+        .clone();
+    let _guard = lock.lock().await;
+
+    let data = match read_cached_download(&url) {
+        Some(data) => data,
+        None => match Downloader::default().download_file(&url, 0).await {
+            Ok(download) => {
+                if let Err(e) = write_cached_download(&url, &download.data) {
+                    warning(format!("Failed to cache {}: {}", url, e));
+                }
+                download.data
+            }
+            Err(e) => return upstream_error_response(&e),
+        },
+    };
+
+    Response::builder()
+        .header(CONTENT_TYPE, "application/octet-stream")
+        .body(Full::new(Bytes::from(data)))
+        .unwrap()
+}
+
+/// Pass a 404 from upstream through, report anything else as a bad gateway
+fn upstream_error_response(error: &anyhow::Error) -> Response<Full<Bytes>> {
+    let status = error.downcast_ref::<RequestError>().and_then(RequestError::status);
+    if status == Some(reqwest::StatusCode::NOT_FOUND) {
+        error_response(StatusCode::NOT_FOUND, "Not Found")
+    } else {
+        error_response(StatusCode::BAD_GATEWAY, &error.to_string())
+    }
+}
+
+fn error_response(status: StatusCode, message: &str) -> Response<Full<Bytes>> {
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Full::new(Bytes::from(json!({ "message": message }).to_string())))
+        .unwrap()
+}
@@ -0,0 +1,123 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use crate::mirrors::MirrorRule;
+use crate::report::warning;
+
+/// User settings, read from `~/.local/share/coolclis/settings.json`
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Maximum number of concurrent requests sent to a single host
+    pub max_connections_per_host: usize,
+    /// Timeout for establishing a connection
+    pub connect_timeout_secs: u64,
+    /// Maximum time without receiving any data before a transfer is considered stalled
+    pub read_timeout_secs: u64,
+    /// Proxy for all requests (http://, https:// or socks5://, credentials in the URL),
+    /// overriding the HTTP_PROXY/HTTPS_PROXY/ALL_PROXY environment variables
+    pub proxy: Option<String>,
+    /// Comma-separated hosts that bypass `proxy`, defaults to the NO_PROXY environment variable
+    pub no_proxy: Option<String>,
+    /// PEM file with additional root certificates to trust
+    pub ca_certificates: Option<PathBuf>,
+    /// PEM file with a client certificate chain for mutual TLS
+    pub client_certificate: Option<PathBuf>,
+    /// PEM file with the PKCS#8 private key of `client_certificate`, if it's not in the same file
+    pub client_key: Option<PathBuf>,
+    /// URL rewrite rules sending API requests and downloads to mirrors
+    pub mirrors: Vec<MirrorRule>,
+    /// Maximum combined rate of all downloads in bytes per second, with an optional
+    /// K, M or G suffix (e.g. `2M`), overridden by `--limit-rate`
+    pub limit_rate: Option<String>,
+    /// Number of previous versions of each tool kept for `rollback`, 0 to keep none
+    pub keep_generations: usize,
+    /// What to do when an installed binary fails to run its probe after install
+    pub smoke_test: SmokeTest,
+    /// Maximum time the probe of an installed binary may take
+    pub probe_timeout_secs: u64,
+    /// Which Linux builds to prefer when a release has both gnu and musl ones
+    pub prefer_libc: PreferLibc,
+    /// Platforms every tool is installed for, behind a dispatcher running the build for the
+    /// machine, for home directories shared by machines of different architectures
+    pub targets: Vec<String>,
+}
+
+/// Preferred C library of Linux builds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PreferLibc {
+    /// gnu builds on glibc systems, musl builds otherwise
+    Auto,
+    Gnu,
+    Musl,
+}
+
+/// Handling of installed binaries that fail to run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SmokeTest {
+    /// Restore the previous version and fail the install
+    Rollback,
+    /// Keep the new version and print a warning
+    Warn,
+    /// Don't run installed binaries
+    Off,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            max_connections_per_host: 8,
+            connect_timeout_secs: 10,
+            read_timeout_secs: 30,
+            proxy: None,
+            no_proxy: None,
+            ca_certificates: None,
+            client_certificate: None,
+            client_key: None,
+            mirrors: Vec::new(),
+            limit_rate: None,
+            keep_generations: 3,
+            smoke_test: SmokeTest::Rollback,
+            probe_timeout_secs: 10,
+            prefer_libc: PreferLibc::Auto,
+            targets: Vec::new(),
+        }
+    }
+}
+
+static SETTINGS: OnceLock<Settings> = OnceLock::new();
+
+pub fn get_settings_path() -> Result<PathBuf> {
+    let home_dir = dirs::home_dir().ok_or_else(|| anyhow!("Failed to determine home directory"))?;
+    Ok(home_dir.join(".local")
+               .join("share")
+               .join("coolclis")
+               .join("settings.json"))
+}
+
+/// Load the settings file, falling back to the defaults if it doesn't exist
+pub fn load_settings() -> Result<Settings> {
+    let settings_path = get_settings_path()?;
+    if !settings_path.exists() {
+        return Ok(Settings::default());
+    }
+
+    let settings_str = fs::read_to_string(&settings_path)?;
+    serde_json::from_str(&settings_str)
+        .map_err(|e| anyhow!("Invalid settings file {}: {}", settings_path.display(), e))
+}
+
+/// Get the settings of this process, loaded once on first use
+pub fn settings() -> &'static Settings {
+    SETTINGS.get_or_init(|| {
+        load_settings().unwrap_or_else(|e| {
+            warning(format!("{}, using default settings", e));
+            Settings::default()
+        })
+    })
+}
@@ -0,0 +1,56 @@
+use anyhow::{anyhow, Context, Result};
+use std::ffi::OsString;
+use std::path::Path;
+
+use crate::exec::run_executable;
+use crate::install::store_project_version;
+use crate::manifest::pinned_version;
+use crate::report::info;
+use crate::staging::write_script;
+use crate::state::load_state;
+use crate::store::{stored_executable, stored_pin};
+
+/// Write the shim of `tool` at `link`: a script running `coolclis shim`, which picks the version
+/// pinned by the nearest project manifest, or the active version outside of projects
+pub fn write_shim(tool: &str, link: &Path) -> Result<()> {
+    let coolclis = std::env::current_exe()?;
+    #[cfg(unix)]
+    let script = format!(
+        "#!/bin/sh\n# coolclis shim, runs the version of {} pinned by the nearest .coolclis.toml\nexec '{}' shim {} -- \"$@\"\n",
+        tool,
+        coolclis.display().to_string().replace('\'', r"'\''"),
+        tool,
+    );
+    #[cfg(not(unix))]
+    let script = format!("@\"{}\" shim {} -- %*\r\n", coolclis.display(), tool);
+
+    write_script(link, &script)
+        .with_context(|| format!("Failed to write the shim {}", link.display()))
+}
+
+/// Run the version of `tool` pinned by the nearest manifest, or its active version,
+/// installing the pinned version first if it's missing
+pub async fn run_shim(tool: &str, args: &[OsString]) -> Result<()> {
+    let state = load_state()?;
+    let tool_state = state.tools.get(tool)
+        .ok_or_else(|| anyhow!("{} was not installed by coolclis", tool))?;
+
+    let (repo, version) = match pinned_version(tool)? {
+        Some(pinned) => (pinned.repo, pinned.version),
+        None => (tool_state.repo.clone(), tool_state.active.clone()),
+    };
+
+    let stored = match stored_pin(tool, &version)? {
+        Some(tag) => stored_executable(tool, &tag)?,
+        None => None,
+    };
+    let executable = match stored {
+        Some(executable) => executable,
+        None => {
+            info(format!("Installing {} {} for this project...", tool, version));
+            store_project_version(tool, &repo, &version, &tool_state.link).await?.1
+        }
+    };
+
+    run_executable(&executable, args)
+}
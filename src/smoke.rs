@@ -0,0 +1,136 @@
+use anyhow::{anyhow, Result};
+use std::io::Read;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use crate::config::resolve_tool;
+use crate::report::{info, warning};
+use crate::rollback::undo_install;
+use crate::settings::{settings, SmokeTest};
+
+/// Arguments installed binaries are run with when the catalog doesn't give any
+const DEFAULT_PROBE: &str = "--version";
+
+/// How running the probe of a binary went
+enum ProbeResult {
+    /// The binary ran; its exit code and combined output
+    Ran { success: bool, output: String },
+    /// The binary could not be started, was killed or timed out
+    Failed(String),
+}
+
+/// Run a freshly installed and activated binary with its probe, `--version` unless its catalog
+/// entry says otherwise, and check that it reports the version of the release.
+///
+/// A binary that doesn't run at all is most likely the wrong file of the release: the install
+/// is undone, unless the `smoke_test` setting says to only warn. A binary that runs but doesn't
+/// report the expected version only gets a warning.
+pub fn smoke_test(tool: &str, repo: &str, version: &str, executable: &Path) -> Result<()> {
+    let Some(failure) = probe_binary(tool, repo, version, executable) else {
+        return Ok(());
+    };
+    let restored = match undo_install(tool, version)? {
+        Some(previous) => format!("rolled back to {}", previous),
+        None => "removed it".to_string(),
+    };
+    Err(anyhow!("Installed {} {} but {}, {}", tool, version, failure, restored))
+}
+
+/// Run `executable` with the probe of `tool` like `smoke_test`, without undoing anything.
+/// Returns why it failed if it doesn't run at all and the `smoke_test` setting says to fail.
+pub fn probe_binary(tool: &str, repo: &str, version: &str, executable: &Path) -> Option<String> {
+    let mode = settings().smoke_test;
+    if mode == SmokeTest::Off {
+        return None;
+    }
+    let probe = resolve_tool(repo).ok()
+        .and_then(|t| t.probe)
+        .unwrap_or_else(|| DEFAULT_PROBE.to_string());
+    let args: Vec<&str> = probe.split_whitespace().collect();
+    if args.is_empty() {
+        return None;
+    }
+
+    let command = format!("{} {}", tool, probe);
+    match run_probe(executable, &args, Duration::from_secs(settings().probe_timeout_secs)) {
+        ProbeResult::Ran { success, output } => {
+            let first_line = output.lines().find(|l| !l.trim().is_empty()).unwrap_or("").trim();
+            if !success {
+                warning(format!("`{}` exited with an error: {}", command, first_line));
+            } else if !reports_version(&output, version) {
+                warning(format!("`{}` doesn't report version {}: {}", command, version, first_line));
+            } else {
+                info(format!("Checked `{}`: {}", command, first_line));
+            }
+            None
+        }
+        ProbeResult::Failed(error) if mode == SmokeTest::Warn => {
+            warning(format!("`{}` failed: {}", command, error));
+            None
+        }
+        ProbeResult::Failed(error) => Some(format!("`{}` failed ({})", command, error)),
+    }
+}
+
+/// Run `executable` with `args`, killing it after `timeout`
+fn run_probe(executable: &Path, args: &[&str], timeout: Duration) -> ProbeResult {
+    let mut child = match Command::new(executable)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(e) => return ProbeResult::Failed(e.to_string()),
+    };
+
+    // Read the output as it comes, a full pipe would block the probe
+    let stdout = child.stdout.take().map(read_in_background);
+    let stderr = child.stderr.take().map(read_in_background);
+
+    let started = Instant::now();
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if started.elapsed() >= timeout => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return ProbeResult::Failed(format!("timed out after {}s", timeout.as_secs()));
+            }
+            Ok(None) => std::thread::sleep(Duration::from_millis(20)),
+            Err(e) => return ProbeResult::Failed(e.to_string()),
+        }
+    };
+
+    let output = [stdout, stderr].into_iter()
+        .flatten()
+        .map(|reader| reader.join().unwrap_or_default())
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    // Killed by a signal, like a crash or an illegal instruction
+    if status.code().is_none() {
+        return ProbeResult::Failed(format!("terminated by {}", status));
+    }
+    ProbeResult::Ran { success: status.success(), output }
+}
+
+fn read_in_background(mut pipe: impl Read + Send + 'static) -> std::thread::JoinHandle<String> {
+    std::thread::spawn(move || {
+        let mut data = Vec::new();
+        let _ = pipe.read_to_end(&mut data);
+        String::from_utf8_lossy(&data).to_string()
+    })
+}
+
+/// Whether the output of the probe mentions the version of a release tag like `v1.2.3`
+/// or `tool-1.2.3`. Tags without a version number can't be checked and always match.
+fn reports_version(output: &str, tag: &str) -> bool {
+    let version = match tag.find(|c: char| c.is_ascii_digit()) {
+        Some(start) => &tag[start..],
+        None => return true,
+    };
+    output.contains(version)
+}
@@ -0,0 +1,136 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Prefix of the staging directories, so leftovers of a killed process can be recognized
+pub const STAGING_PREFIX: &str = ".coolclis-staging-";
+
+/// Staging directories that exist right now, removed by the signal handler on interrupt
+static STAGING_DIRS: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// Private directory next to the final location of an install, where files are prepared
+/// before being renamed into place. Being on the same filesystem makes the rename atomic.
+///
+/// The directory is removed when dropped, whether the install succeeded or not.
+pub struct StagingDir {
+    path: PathBuf,
+}
+
+impl StagingDir {
+    /// Create a staging directory for `name` inside `parent`
+    pub fn new(parent: &Path, name: &str) -> Result<Self> {
+        fs::create_dir_all(parent)?;
+        let path = parent.join(format!("{}{}-{}-{:x}", STAGING_PREFIX, name, std::process::id(), fastrand::u64(..)));
+
+        let mut builder = fs::DirBuilder::new();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::DirBuilderExt;
+            builder.mode(0o700);
+        }
+        builder.create(&path)
+            .with_context(|| format!("Failed to create staging directory in {}", parent.display()))?;
+
+        STAGING_DIRS.lock().unwrap().push(path.clone());
+        Ok(Self { path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for StagingDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+        STAGING_DIRS.lock().unwrap().retain(|p| p != &self.path);
+    }
+}
+
+/// Write an executable script at `path`, atomically replacing whatever is there: the script is
+/// written to a temporary file next to it, made executable, then renamed into place
+pub fn write_script(path: &Path, script: &str) -> std::io::Result<()> {
+    let parent = path.parent().unwrap_or(Path::new("."));
+    fs::create_dir_all(parent)?;
+    let temp_path = parent.join(format!("{}script-{}-{:x}", STAGING_PREFIX, std::process::id(), fastrand::u64(..)));
+
+    let result = fs::write(&temp_path, script).and_then(|_| {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&temp_path, fs::Permissions::from_mode(0o755))?;
+        }
+        fs::rename(&temp_path, path)
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+/// Rename the directory `new` over the existing directory `dest`, setting the old content aside
+/// at `aside` first and putting it back if the rename fails. The signal handler waits for both
+/// renames, so an interrupt can't leave `dest` missing.
+pub fn replace_dir(new: &Path, dest: &Path, aside: &Path) -> std::io::Result<()> {
+    let _staging_dirs = STAGING_DIRS.lock().unwrap();
+    fs::rename(dest, aside)?;
+    fs::rename(new, dest).inspect_err(|_| {
+        let _ = fs::rename(aside, dest);
+    })
+}
+
+/// Remove the staging directories and exit when the process is interrupted with
+/// Ctrl-C or terminated, so an interrupted install leaves nothing behind
+pub async fn cleanup_on_signal() {
+    #[cfg(unix)]
+    let code = {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut terminate = match signal(SignalKind::terminate()) {
+            Ok(terminate) => terminate,
+            Err(_) => return,
+        };
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => 130,
+            _ = terminate.recv() => 143,
+        }
+    };
+    #[cfg(not(unix))]
+    let code = {
+        if tokio::signal::ctrl_c().await.is_err() {
+            return;
+        }
+        130
+    };
+
+    for path in STAGING_DIRS.lock().unwrap().drain(..) {
+        let _ = fs::remove_dir_all(path);
+    }
+    eprintln!("Interrupted");
+    std::process::exit(code);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replace_dir_keeps_the_old_content_on_failure() {
+        let staging = StagingDir::new(&std::env::temp_dir(), "replace-test").unwrap();
+        let dest = staging.path().join("v1.0.0");
+        let new = staging.path().join("new");
+        let aside = staging.path().join("old");
+        fs::create_dir(&dest).unwrap();
+        fs::write(dest.join("tool"), "old").unwrap();
+
+        assert!(replace_dir(&new, &dest, &aside).is_err());
+        assert_eq!(fs::read_to_string(dest.join("tool")).unwrap(), "old");
+        assert!(!aside.exists());
+
+        fs::create_dir(&new).unwrap();
+        fs::write(new.join("tool"), "new").unwrap();
+        replace_dir(&new, &dest, &aside).unwrap();
+        assert_eq!(fs::read_to_string(dest.join("tool")).unwrap(), "new");
+        assert_eq!(fs::read_to_string(aside.join("tool")).unwrap(), "old");
+    }
+}
@@ -0,0 +1,108 @@
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// What coolclis installed, stored in `~/.local/share/coolclis/installed.json`
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct InstallState {
+    pub tools: BTreeMap<String, ToolState>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ToolState {
+    pub repo: String,
+    /// Version the bin entry points to
+    pub active: String,
+    /// Bin entry, a symlink to the executable of the active version
+    pub link: PathBuf,
+    /// Versions in the store
+    pub versions: BTreeMap<String, StoredVersion>,
+    /// Previously active versions, oldest first, restored by `rollback`
+    #[serde(default)]
+    pub history: Vec<String>,
+    /// Whether the bin entry is a shim picking the version from project manifests,
+    /// falling back to the active version
+    #[serde(default)]
+    pub shim: bool,
+}
+
+/// One version of a tool in the store
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct StoredVersion {
+    /// Seconds since the Unix epoch
+    pub installed_at: u64,
+    /// Path of the executable, relative to the version directory
+    pub executable: PathBuf,
+    /// SHA-256 of every file at install time, by path relative to the version directory
+    pub files: BTreeMap<PathBuf, String>,
+    /// Installed for a project manifest, kept in the store whatever the activation history
+    #[serde(default)]
+    pub project: bool,
+    /// Release asset the version was unpacked from, if it was downloaded
+    #[serde(default)]
+    pub asset: Option<SourceAsset>,
+}
+
+/// A downloaded release asset
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SourceAsset {
+    pub name: String,
+    /// SHA-256 of the asset as downloaded
+    pub sha256: String,
+}
+
+/// Serializes the read-modify-write cycles of concurrent installs
+static STATE_LOCK: Mutex<()> = Mutex::new(());
+
+/// Directory of the state of coolclis, ~/.local/share/coolclis
+pub fn get_state_dir() -> Result<PathBuf> {
+    let home_dir = dirs::home_dir().ok_or_else(|| anyhow!("Failed to determine home directory"))?;
+    Ok(home_dir.join(".local").join("share").join("coolclis"))
+}
+
+fn get_state_path() -> Result<PathBuf> {
+    Ok(get_state_dir()?.join("installed.json"))
+}
+
+/// Load the install state, empty if nothing was installed yet
+pub fn load_state() -> Result<InstallState> {
+    let state_path = get_state_path()?;
+    if !state_path.exists() {
+        return Ok(InstallState::default());
+    }
+
+    let state_str = fs::read_to_string(&state_path)?;
+    serde_json::from_str(&state_str)
+        .map_err(|e| anyhow!("Invalid install state {}: {}", state_path.display(), e))
+}
+
+/// Load the install state, change it with `f` and save it again
+pub fn update_state<T>(f: impl FnOnce(&mut InstallState) -> Result<T>) -> Result<T> {
+    let _guard = STATE_LOCK.lock().unwrap();
+    let mut state = load_state()?;
+    let result = f(&mut state)?;
+
+    // Write to a temporary file first, so an interrupted write doesn't lose the state
+    let state_path = get_state_path()?;
+    fs::create_dir_all(get_state_dir()?)?;
+    let temp_path = state_path.with_extension(format!("{}.tmp", std::process::id()));
+    fs::write(&temp_path, serde_json::to_string_pretty(&state)?)?;
+    fs::rename(&temp_path, &state_path)
+        .with_context(|| format!("Failed to write {}", state_path.display()))?;
+
+    Ok(result)
+}
+
+/// Current time in seconds since the Unix epoch
+pub fn now_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/// Format seconds since the Unix epoch for display
+pub fn format_time(secs: u64) -> String {
+    httpdate::fmt_http_date(UNIX_EPOCH + Duration::from_secs(secs))
+}
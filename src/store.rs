@@ -0,0 +1,321 @@
+use anyhow::{anyhow, Context, Result};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::checksum::sha256_hex;
+use crate::report::info;
+use crate::settings::settings;
+use crate::staging::{replace_dir, STAGING_PREFIX};
+use crate::state::{format_time, get_state_dir, load_state, now_secs, update_state, InstallState, StoredVersion, ToolState};
+use crate::version::find_tag;
+
+/// Version recorded for a bin entry that existed before coolclis managed the tool
+pub const UNKNOWN_VERSION: &str = "unknown";
+
+/// Root of the store, where every installed version of a tool lives side by side:
+/// `~/.local/share/coolclis/store/<tool>/<version>/`
+pub fn get_store_dir() -> Result<PathBuf> {
+    Ok(get_state_dir()?.join("store"))
+}
+
+/// Directory of the versions of `tool` in the store
+pub fn tool_store_dir(tool: &str) -> Result<PathBuf> {
+    Ok(get_store_dir()?.join(tool))
+}
+
+/// Directory of one version of `tool` in the store
+pub fn version_dir(tool: &str, version: &str) -> Result<PathBuf> {
+    Ok(tool_store_dir(tool)?.join(version.replace(['/', '\\'], "_")))
+}
+
+/// Move `prepared`, a directory on the same filesystem as the store, into the store as `version`
+/// of `tool`, replacing that version if it was already there. Files identical to the same file
+/// of another stored version are hard-linked to it.
+pub fn add_to_store(tool: &str, version: &str, prepared: &Path, executable: &Path) -> Result<StoredVersion> {
+    let mut files = BTreeMap::new();
+    for relative in list_files(prepared)? {
+        files.insert(relative.clone(), sha256_hex(&fs::read(prepared.join(&relative))?));
+    }
+
+    // Share identical files with the other versions
+    let state = load_state()?;
+    if let Some(tool_state) = state.tools.get(tool) {
+        for (relative, sha256) in &files {
+            let existing = tool_state.versions.iter()
+                .filter(|(other, stored)| other.as_str() != version && stored.files.get(relative) == Some(sha256))
+                .map(|(other, _)| version_dir(tool, other).map(|dir| dir.join(relative)))
+                .find(|path| path.as_ref().is_ok_and(|path| is_same_file(path, &prepared.join(relative), sha256)));
+            if let Some(Ok(existing)) = existing {
+                let path = prepared.join(relative);
+                fs::remove_file(&path)?;
+                if fs::hard_link(&existing, &path).is_err() {
+                    fs::copy(&existing, &path)?;
+                }
+            }
+        }
+    }
+
+    let dest = version_dir(tool, version)?;
+    if dest.exists() {
+        // Set the old content aside next to the prepared one, it's removed with it
+        let old = prepared.with_file_name(format!("{}old-{}", STAGING_PREFIX, fastrand::u64(..)));
+        replace_dir(prepared, &dest, &old)
+            .with_context(|| format!("Failed to move {} into the store", dest.display()))?;
+        let _ = fs::remove_dir_all(&old);
+    } else {
+        fs::rename(prepared, &dest)
+            .with_context(|| format!("Failed to move {} into the store", dest.display()))?;
+    }
+
+    Ok(StoredVersion {
+        installed_at: now_secs(),
+        executable: executable.to_path_buf(),
+        files,
+        project: false,
+        asset: None,
+    })
+}
+
+/// Make `version` the active version of `tool` by pointing its bin entry to it, and remove the
+/// versions no longer needed from the store.
+/// The previously active version is added to the activation history if `remember` is set.
+pub fn activate(state: &mut InstallState, tool: &str, version: &str, remember: bool) -> Result<()> {
+    switch_to(state, tool, version, remember)?;
+    let tool_state = state.tools.get_mut(tool).unwrap();
+    prune(tool, tool_state, settings().keep_generations)
+}
+
+/// Make `version` the active version of `tool`, without removing anything from the store
+fn switch_to(state: &mut InstallState, tool: &str, version: &str, remember: bool) -> Result<()> {
+    let tool_state = state.tools.get_mut(tool)
+        .ok_or_else(|| anyhow!("{} was not installed by coolclis", tool))?;
+    let stored = tool_state.versions.get(version)
+        .ok_or_else(|| anyhow!("{} {} is not installed, install it with: coolclis install {} --version {}",
+            tool, version, tool_state.repo, version))?;
+
+    // A shim finds the active version by itself
+    if !tool_state.shim {
+        link_executable(&version_dir(tool, version)?.join(&stored.executable), &tool_state.link)?;
+    }
+
+    if tool_state.active != version {
+        let previous = std::mem::replace(&mut tool_state.active, version.to_string());
+        if remember {
+            tool_state.history.retain(|v| v != &previous);
+            tool_state.history.push(previous);
+        }
+    }
+    tool_state.history.retain(|v| v != version);
+    Ok(())
+}
+
+/// Keep the last `keep` versions of the activation history, and remove the versions that are
+/// neither active, in the history nor installed for a project from the store
+fn prune(tool: &str, tool_state: &mut ToolState, keep: usize) -> Result<()> {
+    let excess = tool_state.history.len().saturating_sub(keep);
+    tool_state.history.drain(..excess);
+
+    let unused: Vec<String> = tool_state.versions.iter()
+        .filter(|(v, stored)| **v != tool_state.active && !tool_state.history.contains(v) && !stored.project)
+        .map(|(v, _)| v.clone())
+        .collect();
+    for version in unused {
+        tool_state.versions.remove(&version);
+        let _ = fs::remove_dir_all(version_dir(tool, &version)?);
+    }
+    Ok(())
+}
+
+/// Remove the versions of `tool` no longer needed from the store, once a new install is known
+/// to work: until then the previous version must stay, for the install to be undone
+pub fn prune_versions(tool: &str) -> Result<()> {
+    update_state(|state| match state.tools.get_mut(tool) {
+        Some(tool_state) => prune(tool, tool_state, settings().keep_generations),
+        None => Ok(()),
+    })
+}
+
+/// Put the file at the bin entry of `tool` into the store, if it's not a link coolclis made,
+/// so that it's not lost when the entry is replaced
+pub fn adopt_existing(tool: &str, repo: &str, link: &Path) -> Result<()> {
+    let is_file = fs::symlink_metadata(link).is_ok_and(|m| m.file_type().is_file());
+    let state = load_state()?;
+    if !is_file || state.tools.get(tool).is_some_and(|t| t.link == link) {
+        return Ok(());
+    }
+
+    let dest = version_dir(tool, UNKNOWN_VERSION)?;
+    fs::create_dir_all(&dest)?;
+    fs::copy(link, dest.join(tool))?;
+    let stored = StoredVersion {
+        installed_at: 0,
+        executable: PathBuf::from(tool),
+        files: BTreeMap::from([(PathBuf::from(tool), sha256_hex(&fs::read(link)?))]),
+        project: false,
+        asset: None,
+    };
+
+    update_state(|state| {
+        let tool_state = state.tools.entry(tool.to_string()).or_insert_with(|| ToolState {
+            repo: repo.to_string(),
+            active: UNKNOWN_VERSION.to_string(),
+            link: link.to_path_buf(),
+            versions: BTreeMap::new(),
+            history: Vec::new(),
+            shim: false,
+        });
+        tool_state.versions.insert(UNKNOWN_VERSION.to_string(), stored);
+        if tool_state.active != UNKNOWN_VERSION {
+            tool_state.history.push(UNKNOWN_VERSION.to_string());
+        }
+        Ok(())
+    })
+}
+
+/// Record a version added to the store and make it the active one. Nothing is removed from the
+/// store until `prune_versions` is called.
+pub fn record_install(tool: &str, repo: &str, version: &str, stored: StoredVersion, link: &Path) -> Result<()> {
+    update_state(|state| add_version(state, tool, repo, version, stored, link))
+}
+
+/// Add `version` to the state of `tool` and make it the active one
+pub fn add_version(state: &mut InstallState, tool: &str, repo: &str, version: &str, stored: StoredVersion, link: &Path) -> Result<()> {
+    let tool_state = state.tools.entry(tool.to_string()).or_insert_with(|| ToolState {
+        repo: repo.to_string(),
+        active: version.to_string(),
+        link: link.to_path_buf(),
+        versions: BTreeMap::new(),
+        history: Vec::new(),
+        shim: false,
+    });
+    tool_state.repo = repo.to_string();
+    tool_state.link = link.to_path_buf();
+    tool_state.versions.insert(version.to_string(), stored);
+    switch_to(state, tool, version, true)
+}
+
+/// Record a version of a tool used by a project manifest, `stored` if it was just added to the
+/// store, without changing the active version. The tool becomes a shim at `link`; its first
+/// version is also the active one.
+pub fn record_project_version(tool: &str, repo: &str, version: &str, stored: Option<StoredVersion>, link: &Path) -> Result<()> {
+    update_state(|state| {
+        let tool_state = state.tools.entry(tool.to_string()).or_insert_with(|| ToolState {
+            repo: repo.to_string(),
+            active: version.to_string(),
+            link: link.to_path_buf(),
+            versions: BTreeMap::new(),
+            history: Vec::new(),
+            shim: true,
+        });
+        tool_state.link = link.to_path_buf();
+        tool_state.shim = true;
+        if let Some(stored) = stored {
+            tool_state.versions.insert(version.to_string(), stored);
+        }
+        if let Some(stored) = tool_state.versions.get_mut(version) {
+            stored.project = true;
+        }
+        Ok(())
+    })
+}
+
+/// Path of the executable of an installed version of a tool, if it's in the store
+pub fn stored_executable(tool: &str, version: &str) -> Result<Option<PathBuf>> {
+    let state = load_state()?;
+    let Some(stored) = state.tools.get(tool).and_then(|t| t.versions.get(version)) else {
+        return Ok(None);
+    };
+    Ok(Some(version_dir(tool, version)?.join(&stored.executable)))
+}
+
+/// The version of `tool` in the store that a project pins with `pin`: the pin itself, or the tag
+/// of its release when the pin is normalized, like `v1.18.0` for `1.18`
+pub fn stored_pin(tool: &str, pin: &str) -> Result<Option<String>> {
+    let state = load_state()?;
+    let Some(tool_state) = state.tools.get(tool) else {
+        return Ok(None);
+    };
+    Ok(find_tag(tool_state.versions.keys().map(String::as_str), pin, tool).map(str::to_string))
+}
+
+/// Switch the active version of a tool to another installed version, given as its tag or a
+/// version matching it, like `1.18.0` for `v1.18.0`
+pub fn use_version(tool: &str, version: &str) -> Result<()> {
+    let tag = update_state(|state| {
+        let tag = state.tools.get(tool)
+            .and_then(|t| find_tag(t.versions.keys().map(String::as_str), version, tool))
+            .unwrap_or(version)
+            .to_string();
+        activate(state, tool, &tag, true)?;
+        Ok(tag)
+    })?;
+    info(format!("Now using {} {}", tool, tag));
+    Ok(())
+}
+
+/// Print the versions of a tool in the store
+pub fn list_installed_versions(tool: &str) -> Result<()> {
+    let state = load_state()?;
+    let tool_state = state.tools.get(tool)
+        .ok_or_else(|| anyhow!("{} was not installed by coolclis", tool))?;
+
+    println!("{:<15} {:<8} INSTALLED", "VERSION", "ACTIVE");
+    println!("{:<15} {:<8} ---------", "-------", "------");
+    for (version, stored) in &tool_state.versions {
+        let active = if *version == tool_state.active { "*" } else { "" };
+        let installed = match stored.installed_at {
+            0 => "-".to_string(),
+            secs => format_time(secs),
+        };
+        println!("{:<15} {:<8} {}", version, active, installed);
+    }
+
+    Ok(())
+}
+
+/// Point `link` to `executable`, atomically replacing whatever is there
+pub fn link_executable(executable: &Path, link: &Path) -> Result<()> {
+    let parent = link.parent().ok_or_else(|| anyhow!("Invalid bin entry {}", link.display()))?;
+    fs::create_dir_all(parent)?;
+    let temp_link = parent.join(format!("{}link-{}-{:x}", STAGING_PREFIX, std::process::id(), fastrand::u64(..)));
+
+    // Without symlinks, the bin entry is a copy of the executable
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(executable, &temp_link)?;
+    #[cfg(not(unix))]
+    fs::copy(executable, &temp_link)?;
+
+    fs::rename(&temp_link, link).inspect_err(|_| {
+        let _ = fs::remove_file(&temp_link);
+    })
+    .with_context(|| format!("Failed to link {}", link.display()))
+}
+
+/// Paths of all files below `dir`, relative to it
+pub fn list_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut pending = vec![PathBuf::new()];
+    while let Some(relative) = pending.pop() {
+        for entry in fs::read_dir(dir.join(&relative))? {
+            let entry = entry?;
+            let path = relative.join(entry.file_name());
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                pending.push(path);
+            } else if file_type.is_file() {
+                files.push(path);
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// Whether the stored file at `existing` still has the content `sha256` of `new`
+fn is_same_file(existing: &Path, new: &Path, sha256: &str) -> bool {
+    let same_size = match (fs::metadata(existing), fs::metadata(new)) {
+        (Ok(a), Ok(b)) => a.len() == b.len(),
+        _ => false,
+    };
+    same_size && fs::read(existing).is_ok_and(|data| sha256_hex(&data) == sha256)
+}
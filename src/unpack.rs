@@ -3,8 +3,8 @@ use std::fs;
 use std::io::Cursor;
 use std::path::{Path, PathBuf};
 
-/// Extract an archive and find the executable within it
-pub fn extract_archive(data: &[u8], filename: &str, dest_dir: &Path) -> Result<Option<PathBuf>> {
+/// Extract an archive and find the executable within it, preferably one named `exe_name`
+pub fn extract_archive(data: &[u8], filename: &str, dest_dir: &Path, exe_name: &str) -> Result<Option<PathBuf>> {
     let cursor = Cursor::new(data);
 
     if filename.ends_with(".tar.gz") || filename.ends_with(".tgz") {
@@ -13,27 +13,21 @@ pub fn extract_archive(data: &[u8], filename: &str, dest_dir: &Path) -> Result<O
         archive.unpack(dest_dir)?;
 
         // Find executable files recursively
-        find_executable_recursively(dest_dir)
+        find_executable_recursively(dest_dir, exe_name)
     } else if filename.ends_with(".zip") {
         let mut archive = zip::ZipArchive::new(cursor)?;
         archive.extract(dest_dir)?;
 
         // Find executable files recursively
-        find_executable_recursively(dest_dir)
+        find_executable_recursively(dest_dir, exe_name)
     } else {
         // Not an archive, just a binary
         Ok(None)
     }
 }
 
-/// Find an executable file within a directory structure
-pub fn find_executable_recursively(dir: &Path) -> Result<Option<PathBuf>> {
-    let exe_name = dir.file_name()
-        .and_then(|n| n.to_str())
-        .unwrap_or("")
-        .strip_suffix("_temp")
-        .unwrap_or("");
-
+/// Find an executable file within a directory structure, preferably one named `exe_name`
+pub fn find_executable_recursively(dir: &Path, exe_name: &str) -> Result<Option<PathBuf>> {
     // First, check if there's a bin directory with the executable
     let bin_dir = dir.join("bin");
     if bin_dir.exists() && bin_dir.is_dir() {
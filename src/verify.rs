@@ -0,0 +1,283 @@
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::checksum::{find_sha256, sha256_hex};
+use crate::downloader::Downloader;
+use crate::github::{get_specific_release, Asset, Release};
+use crate::report::warning;
+use crate::state::{load_state, StoredVersion, ToolState};
+use crate::store::{list_files, version_dir, UNKNOWN_VERSION};
+
+/// State of an installed file compared to install time
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileStatus {
+    Ok,
+    /// The content changed, or the bin entry no longer points to the store
+    Modified,
+    Missing,
+    /// A file that was not installed
+    Unknown,
+}
+
+/// Result of the comparison of an installed asset with the checksums published with the release
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UpstreamStatus {
+    Match,
+    Mismatch,
+    /// No checksum to compare with
+    Unavailable,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FileReport {
+    pub path: PathBuf,
+    pub status: FileStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expected: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub actual: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UpstreamReport {
+    pub status: UpstreamStatus,
+    /// Checksum file the asset was found in, or why there is nothing to compare with
+    pub detail: String,
+}
+
+/// Verification of one version of a tool in the store
+#[derive(Debug, Serialize)]
+pub struct VersionReport {
+    pub tool: String,
+    pub version: String,
+    pub active: bool,
+    pub ok: bool,
+    /// Files that are not as installed, all of them are fine otherwise
+    pub files: Vec<FileReport>,
+    pub checked_files: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upstream: Option<UpstreamReport>,
+}
+
+/// Re-hash the installed files of `tools` (all installed tools if empty) and compare them with
+/// the hashes recorded at install time, and with the upstream release checksums if `upstream`.
+/// Fails if any file was modified, is missing or unknown, or doesn't match upstream.
+pub async fn verify_tools(tools: &[String], json: bool, upstream: bool) -> Result<()> {
+    let state = load_state()?;
+    for tool in tools {
+        if !state.tools.contains_key(tool) {
+            return Err(anyhow!("{} was not installed by coolclis", tool));
+        }
+    }
+
+    let mut reports = Vec::new();
+    for (tool, tool_state) in &state.tools {
+        if !tools.is_empty() && !tools.contains(tool) {
+            continue;
+        }
+        for (version, stored) in &tool_state.versions {
+            let mut report = verify_version(tool, tool_state, version, stored)?;
+            if upstream {
+                let upstream_report = verify_upstream(&tool_state.repo, version, stored).await;
+                report.ok &= upstream_report.status != UpstreamStatus::Mismatch;
+                report.upstream = Some(upstream_report);
+            }
+            reports.push(report);
+        }
+    }
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&reports)?);
+    } else {
+        print_reports(&reports);
+    }
+
+    let failed = reports.iter().filter(|r| !r.ok).count();
+    if failed > 0 {
+        return Err(anyhow!("{} of {} installed versions failed verification", failed, reports.len()));
+    }
+    Ok(())
+}
+
+/// Compare the files of a version in the store, and the bin entry if it's the active version,
+/// with what was installed
+fn verify_version(tool: &str, tool_state: &ToolState, version: &str, stored: &StoredVersion) -> Result<VersionReport> {
+    let dir = version_dir(tool, version)?;
+    let mut files = Vec::new();
+
+    for (relative, expected) in &stored.files {
+        let (status, actual) = match fs::read(dir.join(relative)) {
+            Ok(data) => {
+                let actual = sha256_hex(&data);
+                let status = if actual == *expected { FileStatus::Ok } else { FileStatus::Modified };
+                (status, Some(actual))
+            }
+            Err(_) => (FileStatus::Missing, None),
+        };
+        if status != FileStatus::Ok {
+            files.push(FileReport { path: dir.join(relative), status, expected: Some(expected.clone()), actual });
+        }
+    }
+
+    let present = if dir.is_dir() { list_files(&dir)? } else { Vec::new() };
+    for relative in present {
+        if !stored.files.contains_key(&relative) {
+            let actual = fs::read(dir.join(&relative)).ok().map(|data| sha256_hex(&data));
+            files.push(FileReport { path: dir.join(relative), status: FileStatus::Unknown, expected: None, actual });
+        }
+    }
+
+    let active = tool_state.active == version;
+    if active {
+        if let Some(status) = check_bin_entry(tool, tool_state, &dir.join(&stored.executable)) {
+            files.push(FileReport { path: tool_state.link.clone(), status, expected: None, actual: None });
+        }
+    }
+
+    Ok(VersionReport {
+        tool: tool.to_string(),
+        version: version.to_string(),
+        active,
+        ok: files.is_empty(),
+        files,
+        checked_files: stored.files.len(),
+        upstream: None,
+    })
+}
+
+/// Problem with the bin entry of a tool, which must be a symlink to `executable` or the shim
+fn check_bin_entry(tool: &str, tool_state: &ToolState, executable: &Path) -> Option<FileStatus> {
+    let link = &tool_state.link;
+    if fs::symlink_metadata(link).is_err() {
+        return Some(FileStatus::Missing);
+    }
+
+    let expected = if tool_state.shim {
+        fs::read_to_string(link).is_ok_and(|script| script.contains(&format!(" shim {} -- ", tool)))
+    } else if cfg!(unix) {
+        fs::read_link(link).is_ok_and(|target| target == executable)
+    } else {
+        // Without symlinks, the bin entry is a copy of the executable
+        matches!((fs::read(link), fs::read(executable)), (Ok(a), Ok(b)) if a == b)
+    };
+    (!expected).then_some(FileStatus::Modified)
+}
+
+/// Compare the asset a version was installed from with the checksums published with its release
+async fn verify_upstream(repo: &str, version: &str, stored: &StoredVersion) -> UpstreamReport {
+    let unavailable = |detail: &str| UpstreamReport { status: UpstreamStatus::Unavailable, detail: detail.to_string() };
+
+    if version == UNKNOWN_VERSION {
+        return unavailable("not installed by coolclis");
+    }
+    let Some(asset) = &stored.asset else {
+        return unavailable("the asset was not recorded at install time");
+    };
+    let release = match get_specific_release(repo, version).await {
+        Ok(release) => release,
+        Err(e) => return unavailable(&format!("{:#}", e)),
+    };
+
+    for checksum_asset in checksum_assets(&release, &asset.name) {
+        let checksums = match Downloader::default().download_file(&checksum_asset.browser_download_url, checksum_asset.size).await {
+            Ok(download) => String::from_utf8_lossy(&download.data).to_string(),
+            Err(e) => {
+                warning(format!("Failed to download {}: {:#}", checksum_asset.name, e));
+                continue;
+            }
+        };
+        if let Some(published) = find_sha256(&checksums, &asset.name) {
+            let status = if published == asset.sha256 { UpstreamStatus::Match } else { UpstreamStatus::Mismatch };
+            return UpstreamReport { status, detail: checksum_asset.name.clone() };
+        }
+    }
+
+    unavailable(&format!("no published checksum of {}", asset.name))
+}
+
+/// Assets of a release that may hold the checksum of `asset_name`, most specific first
+fn checksum_assets<'a>(release: &'a Release, asset_name: &str) -> Vec<&'a Asset> {
+    let dedicated: Vec<String> = ["sha256", "sha256sum"].iter()
+        .map(|ext| format!("{}.{}", asset_name, ext))
+        .collect();
+    let is_list = |name: &str| {
+        let name = name.to_lowercase();
+        let is_signature = [".sig", ".asc", ".pem", ".minisig"].iter().any(|ext| name.ends_with(ext));
+        (name.contains("checksums") || name.contains("sha256sums")) && !is_signature
+    };
+
+    let mut assets: Vec<&Asset> = release.assets.iter()
+        .filter(|a| dedicated.contains(&a.name))
+        .collect();
+    assets.extend(release.assets.iter().filter(|a| is_list(&a.name) && !dedicated.contains(&a.name)));
+    assets
+}
+
+fn print_reports(reports: &[VersionReport]) {
+    for report in reports {
+        let active = if report.active { " (active)" } else { "" };
+        let result = if report.ok { "ok" } else { "FAILED" };
+        println!("{} {}{}: {}, {} files checked", report.tool, report.version, active, result, report.checked_files);
+
+        for file in &report.files {
+            let status = match file.status {
+                FileStatus::Ok => "ok",
+                FileStatus::Modified => "modified",
+                FileStatus::Missing => "missing",
+                FileStatus::Unknown => "unknown",
+            };
+            println!("  {:<9} {}", status, file.path.display());
+        }
+
+        if let Some(upstream) = &report.upstream {
+            let status = match upstream.status {
+                UpstreamStatus::Match => "matches the published checksum in",
+                UpstreamStatus::Mismatch => "DOES NOT MATCH the published checksum in",
+                UpstreamStatus::Unavailable => "can't be compared with upstream:",
+            };
+            println!("  asset {} {}", status, upstream.detail);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn release(assets: &[&str]) -> Release {
+        Release {
+            tag_name: "v1.0.0".to_string(),
+            assets: assets.iter().map(|name| Asset {
+                name: name.to_string(),
+                browser_download_url: format!("https://example.com/{}", name),
+                size: 1,
+            }).collect(),
+            prerelease: false,
+            draft: false,
+        }
+    }
+
+    #[test]
+    fn checksum_assets_cases() {
+        let cases: &[(&[&str], &[&str])] = &[
+            (&["tool.tar.gz", "tool.tar.gz.sha256"], &["tool.tar.gz.sha256"]),
+            (&["tool.tar.gz", "tool.tar.gz.sha256sum"], &["tool.tar.gz.sha256sum"]),
+            (&["tool.tar.gz", "SHA256SUMS"], &["SHA256SUMS"]),
+            (&["tool.tar.gz", "tool_1.0.0_checksums.txt"], &["tool_1.0.0_checksums.txt"]),
+            // The dedicated file comes first
+            (&["SHA256SUMS", "tool.tar.gz", "tool.tar.gz.sha256"], &["tool.tar.gz.sha256", "SHA256SUMS"]),
+            // Signatures and the checksums of other assets are not checksums of this one
+            (&["tool.tar.gz", "SHA256SUMS.sig", "checksums.txt.asc", "other.zip.sha256"], &[]),
+            (&["tool.tar.gz"], &[]),
+        ];
+        for (assets, expected) in cases {
+            let release = release(assets);
+            let found: Vec<&str> = checksum_assets(&release, "tool.tar.gz").iter().map(|a| a.name.as_str()).collect();
+            assert_eq!(found, *expected, "{:?}", assets);
+        }
+    }
+}
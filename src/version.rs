@@ -0,0 +1,373 @@
+use anyhow::{anyhow, Context, Result};
+use semver::{BuildMetadata, Prerelease, Version, VersionReq};
+use std::fmt;
+
+use crate::github::{get_latest_release, get_specific_release, list_releases, Release};
+
+/// The release of a tool to install, as given with `--version`, `tool@version` or in a manifest
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VersionRequirement {
+    /// The release the repository marks as latest, never a prerelease
+    Latest,
+    /// The highest version, prereleases included
+    LatestPrerelease,
+    /// The highest stable version matching a semver range, like `^1.4`, `~0.22` or `1.x`
+    Range(VersionReq),
+    /// A tag, or a version that tags like `v1.2.3` or `tool-1.2.3` are normalized to
+    Exact(String),
+}
+
+impl VersionRequirement {
+    pub fn parse(spec: &str) -> Result<Self> {
+        let spec = spec.trim();
+        let is_wildcard = |part: &str| matches!(part, "x" | "X" | "*");
+        match spec {
+            "" => Err(anyhow!("Empty version")),
+            "latest" => Ok(Self::Latest),
+            "latest-prerelease" => Ok(Self::LatestPrerelease),
+            _ if spec.starts_with(['^', '~', '=', '>', '<', '*']) || spec.contains(',') || spec.split('.').any(is_wildcard) => {
+                let requirement = VersionReq::parse(spec)
+                    .with_context(|| format!("Invalid version requirement '{}'", spec))?;
+                Ok(Self::Range(requirement))
+            }
+            _ => Ok(Self::Exact(spec.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for VersionRequirement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Latest => write!(f, "latest"),
+            Self::LatestPrerelease => write!(f, "latest-prerelease"),
+            Self::Range(requirement) => write!(f, "{}", requirement),
+            Self::Exact(tag) => write!(f, "{}", tag),
+        }
+    }
+}
+
+/// Find the release of `repo` matching a version requirement. Ranges and prereleases need the
+/// list of releases, the latest release and exact tags are looked up directly.
+pub async fn find_release(repo: &str, requirement: &VersionRequirement) -> Result<Release> {
+    let tool = repo.split('/').next_back().unwrap_or(repo);
+    match requirement {
+        VersionRequirement::Latest => get_latest_release(repo).await,
+        VersionRequirement::Exact(tag) => {
+            let error = match get_specific_release(repo, tag).await {
+                Ok(release) => return Ok(release),
+                Err(e) => e,
+            };
+            // `1.2.3` for a repository tagging `v1.2.3`, or the other way around
+            let Some((_, wanted)) = split_tag(tag) else {
+                return Err(error);
+            };
+            let Ok(releases) = list_releases(repo).await else {
+                return Err(error);
+            };
+            versioned_releases(releases, tool).into_iter()
+                .find(|(_, version)| *version == wanted)
+                .map(|(release, _)| release)
+                .ok_or(error)
+        }
+        VersionRequirement::LatestPrerelease | VersionRequirement::Range(_) => {
+            let releases: Vec<Release> = list_releases(repo).await?
+                .into_iter()
+                .filter(|r| !r.draft)
+                .collect();
+            let latest_tag = releases.first().map(|r| r.tag_name.clone());
+            select_release(releases, tool, requirement).ok_or_else(|| match latest_tag {
+                Some(tag) => anyhow!("No release of {} matches {} (the most recent is {})", repo, requirement, tag),
+                None => anyhow!("{} has no releases", repo),
+            })
+        }
+    }
+}
+
+/// The release with the highest version: among the stable ones matching a range, or among all of
+/// them for the latest prerelease
+fn select_release(releases: Vec<Release>, tool: &str, requirement: &VersionRequirement) -> Option<Release> {
+    let versioned = versioned_releases(releases, tool).into_iter();
+    let found = match requirement {
+        VersionRequirement::Range(range) => versioned
+            .filter(|(release, version)| !release.prerelease && range.matches(version))
+            .max_by(|(_, a), (_, b)| a.cmp(b)),
+        _ => versioned.max_by(|(_, a), (_, b)| a.cmp(b)),
+    };
+    found.map(|(release, _)| release)
+}
+
+/// Whether `tag` is a tag of `tool` for the version `wanted` stands for, like `v1.18.0` or
+/// `tool-1.18.0` for `1.18`
+pub fn tag_matches(tag: &str, wanted: &str, tool: &str) -> bool {
+    let (Some((prefix, version)), Some((_, wanted))) = (split_tag(tag), split_tag(wanted)) else {
+        return false;
+    };
+    version == wanted && (prefix.is_empty() || prefix.eq_ignore_ascii_case(tool))
+}
+
+/// The tag among `tags` for the version `wanted`: `wanted` itself, or a tag matching it
+/// like `v1.18.0` for `1.18`
+pub fn find_tag<'a>(tags: impl IntoIterator<Item = &'a str>, wanted: &str, tool: &str) -> Option<&'a str> {
+    let tags: Vec<&str> = tags.into_iter().collect();
+    tags.iter().find(|tag| **tag == wanted)
+        .or_else(|| tags.iter().find(|tag| tag_matches(tag, wanted, tool)))
+        .copied()
+}
+
+/// Releases with the version of their tag, skipping drafts and tags that aren't versions.
+/// When some tags are prefixed with the name of the tool, like `tool-v1.2.3` in a repository
+/// releasing several tools, only those and unprefixed tags are kept.
+fn versioned_releases(releases: Vec<Release>, tool: &str) -> Vec<(Release, Version)> {
+    let versioned: Vec<(Release, String, Version)> = releases.into_iter()
+        .filter(|r| !r.draft)
+        .filter_map(|r| {
+            let (prefix, version) = split_tag(&r.tag_name)?;
+            let prefix = prefix.to_lowercase();
+            Some((r, prefix, version))
+        })
+        .collect();
+
+    let tool = tool.to_lowercase();
+    let has_tool_prefix = versioned.iter().any(|(_, prefix, _)| *prefix == tool);
+    versioned.into_iter()
+        .filter(|(_, prefix, _)| !has_tool_prefix || prefix.is_empty() || *prefix == tool)
+        .map(|(release, _, version)| (release, version))
+        .collect()
+}
+
+/// Split a release tag into the name it's prefixed with, empty if none, and the version it
+/// stands for: `v1.2.3`, `1.2`, `tool-v1.2.3`, `tool_1.2.3-rc.1`, or a date like `2024-01-15`
+fn split_tag(tag: &str) -> Option<(&str, Version)> {
+    let tag = tag.trim();
+    let start = tag.find(|c: char| c.is_ascii_digit())?;
+    let prefix = tag[..start].strip_suffix(['v', 'V']).unwrap_or(&tag[..start]);
+    let name = match prefix.strip_suffix(['-', '_', '/', '@']) {
+        Some(name) if !name.is_empty() => name,
+        _ if prefix.is_empty() => "",
+        _ => return None,
+    };
+    Some((name, parse_version(&tag[start..])?))
+}
+
+/// Parse a version more leniently than semver: one to three numbers with leading zeros
+/// allowed, an optional prerelease (`-rc.1`, or `rc1` directly after the numbers) and build
+fn parse_version(version: &str) -> Option<Version> {
+    if let Some(date) = parse_date(version) {
+        return Some(date);
+    }
+
+    let core_end = version.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(version.len());
+    let (core, rest) = version.split_at(core_end);
+    let numbers: Vec<u64> = core.split('.').map(|n| n.parse().ok()).collect::<Option<_>>()?;
+    if numbers.len() > 3 {
+        return None;
+    }
+
+    let (pre, build) = match rest.split_once('+') {
+        Some((pre, build)) => (pre, BuildMetadata::new(build).ok()?),
+        None => (rest, BuildMetadata::EMPTY),
+    };
+    let pre = pre.strip_prefix('-').unwrap_or(pre);
+    Some(Version {
+        major: numbers[0],
+        minor: numbers.get(1).copied().unwrap_or(0),
+        patch: numbers.get(2).copied().unwrap_or(0),
+        pre: if pre.is_empty() { Prerelease::EMPTY } else { Prerelease::new(pre).ok()? },
+        build,
+    })
+}
+
+/// Date versions, `2024-01-15`, `2024.01.15` or `20240115`, as `2024.1.15`
+fn parse_date(version: &str) -> Option<Version> {
+    let parts: Vec<&str> = if version.len() == 8 && version.chars().all(|c| c.is_ascii_digit()) {
+        vec![&version[..4], &version[4..6], &version[6..]]
+    } else {
+        version.split(['-', '.']).collect()
+    };
+    let [year, month, day] = parts[..] else {
+        return None;
+    };
+    if year.len() != 4 || month.len() != 2 || day.len() != 2 {
+        return None;
+    }
+    let (year, month, day): (u64, u64, u64) = (year.parse().ok()?, month.parse().ok()?, day.parse().ok()?);
+    ((1970..=9999).contains(&year) && (1..=12).contains(&month) && (1..=31).contains(&day))
+        .then(|| Version::new(year, month, day))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn release(tag: &str, prerelease: bool) -> Release {
+        Release { tag_name: tag.to_string(), assets: Vec::new(), prerelease, draft: false }
+    }
+
+    fn selected(tags: &[(&str, bool)], tool: &str, requirement: &str) -> Option<String> {
+        let releases = tags.iter().map(|(tag, prerelease)| release(tag, *prerelease)).collect();
+        select_release(releases, tool, &VersionRequirement::parse(requirement).unwrap()).map(|r| r.tag_name)
+    }
+
+    #[test]
+    fn split_tags() {
+        for (tag, prefix, version) in [
+            ("1.2.3", "", "1.2.3"),
+            ("v1.2.3", "", "1.2.3"),
+            ("V1.2.3", "", "1.2.3"),
+            ("v1.2", "", "1.2.0"),
+            ("14", "", "14.0.0"),
+            ("tool-v1.2.3", "tool", "1.2.3"),
+            ("tool-1.2.3", "tool", "1.2.3"),
+            ("tool_1.2.3-rc.1", "tool", "1.2.3-rc.1"),
+            ("tool/v1.2.3", "tool", "1.2.3"),
+            ("tool@1.2.3", "tool", "1.2.3"),
+            ("v2.0.0-beta.2+build.5", "", "2.0.0-beta.2+build.5"),
+            ("2024-01-15", "", "2024.1.15"),
+            ("20240115", "", "2024.1.15"),
+        ] {
+            let (found_prefix, found_version) = split_tag(tag).unwrap_or_else(|| panic!("{}", tag));
+            assert_eq!((found_prefix, found_version.to_string().as_str()), (prefix, version), "{}", tag);
+        }
+    }
+
+    #[test]
+    fn split_invalid_tags() {
+        for tag in ["latest", "nightly", "release", "1.2.3.4", "v", "tool1.2.3", "-1.2.3"] {
+            assert!(split_tag(tag).is_none(), "{}", tag);
+        }
+    }
+
+    #[test]
+    fn parse_versions() {
+        for (version, expected) in [
+            ("1.2.3", "1.2.3"),
+            ("01.02.03", "1.2.3"),
+            ("1.2.3-rc.1", "1.2.3-rc.1"),
+            ("1.2.3rc1", "1.2.3-rc1"),
+            ("0.22", "0.22.0"),
+            ("2024.01.15", "2024.1.15"),
+        ] {
+            assert_eq!(parse_version(version).map(|v| v.to_string()).as_deref(), Some(expected), "{}", version);
+        }
+        for version in ["", "a.b.c", "1..2", "1.2.3.4", "1.2.3-rc..1"] {
+            assert!(parse_version(version).is_none(), "{}", version);
+        }
+    }
+
+    #[test]
+    fn prereleases_come_before_their_release() {
+        let mut versions: Vec<Version> = ["1.0.0", "1.0.0-rc.2", "1.0.0-alpha", "1.0.0-rc.10", "0.9.9", "1.0.0-beta.1"]
+            .iter()
+            .map(|v| parse_version(v).unwrap())
+            .collect();
+        versions.sort();
+        let sorted: Vec<String> = versions.iter().map(Version::to_string).collect();
+        assert_eq!(sorted, ["0.9.9", "1.0.0-alpha", "1.0.0-beta.1", "1.0.0-rc.2", "1.0.0-rc.10", "1.0.0"]);
+    }
+
+    #[test]
+    fn parse_requirements() {
+        assert_eq!(VersionRequirement::parse("latest").unwrap(), VersionRequirement::Latest);
+        assert_eq!(VersionRequirement::parse(" latest-prerelease ").unwrap(), VersionRequirement::LatestPrerelease);
+        for (spec, tag) in [("1.2.3", "1.2.3"), ("v1.2.3", "v1.2.3"), ("tool-v1.2.3", "tool-v1.2.3"), ("nightly", "nightly")] {
+            assert_eq!(VersionRequirement::parse(spec).unwrap(), VersionRequirement::Exact(tag.to_string()), "{}", spec);
+        }
+        for spec in ["^1.4", "~0.22", "1.x", "1.2.*", "*", ">=1.2, <2", "=1.2.3"] {
+            assert!(matches!(VersionRequirement::parse(spec).unwrap(), VersionRequirement::Range(_)), "{}", spec);
+        }
+        for spec in ["", "  ", "^", "~x.y", ">=1.2 <2"] {
+            assert!(VersionRequirement::parse(spec).is_err(), "{}", spec);
+        }
+    }
+
+    #[test]
+    fn requirements_display_as_given() {
+        for spec in ["latest", "latest-prerelease", "v1.2.3", "^1.4", "~0.22"] {
+            assert_eq!(VersionRequirement::parse(spec).unwrap().to_string(), spec);
+        }
+    }
+
+    #[test]
+    fn exact_versions_match_their_tags() {
+        for (tag, wanted) in [("v1.2.3", "1.2.3"), ("1.2.3", "v1.2.3"), ("v1.18.0", "1.18"), ("tool-v1.2.3", "1.2.3"), ("Tool_1.2.3", "v1.2.3")] {
+            assert!(tag_matches(tag, wanted, "tool"), "{} for {}", tag, wanted);
+        }
+        for (tag, wanted) in [("v1.2.4", "1.2.3"), ("other-v1.2.3", "1.2.3"), ("v1.2.3-rc.1", "1.2.3"), ("nightly", "nightly")] {
+            assert!(!tag_matches(tag, wanted, "tool"), "{} for {}", tag, wanted);
+        }
+    }
+
+    #[test]
+    fn ranges_select_the_highest_stable_match() {
+        let tags = [
+            ("v1.5.0-rc.1", true),
+            ("v1.4.2", false),
+            ("v2.0.0", false),
+            ("v1.4.10", false),
+            ("v1.3.0", false),
+            ("v0.22.9", false),
+            ("v0.22.1", false),
+            ("v0.23.0", false),
+        ];
+        for (requirement, expected) in [
+            ("^1.4", Some("v1.4.10")),
+            ("~0.22", Some("v0.22.9")),
+            ("^0.22", Some("v0.22.9")),
+            ("1.x", Some("v1.4.10")),
+            ("*", Some("v2.0.0")),
+            (">=1.3, <1.4.5", Some("v1.4.2")),
+            ("^3", None),
+        ] {
+            assert_eq!(selected(&tags, "tool", requirement).as_deref(), expected, "{}", requirement);
+        }
+    }
+
+    /// A tag GitHub marks as a prerelease is skipped by ranges even when its version looks stable
+    #[test]
+    fn ranges_skip_releases_marked_as_prereleases() {
+        let tags = [("v1.5.0", true), ("v1.4.0", false)];
+        assert_eq!(selected(&tags, "tool", "^1").as_deref(), Some("v1.4.0"));
+    }
+
+    #[test]
+    fn latest_prerelease_is_the_highest_version() {
+        let tags = [("v1.4.0", false), ("v1.5.0-rc.2", true), ("v1.5.0-rc.10", true), ("v1.5.0-beta.1", true)];
+        assert_eq!(selected(&tags, "tool", "latest-prerelease").as_deref(), Some("v1.5.0-rc.10"));
+
+        let tags = [("v1.5.0-rc.1", true), ("v1.5.0", false)];
+        assert_eq!(selected(&tags, "tool", "latest-prerelease").as_deref(), Some("v1.5.0"));
+    }
+
+    /// In a repository releasing several tools, only the tags of this tool and unprefixed ones count
+    #[test]
+    fn versions_given_to_use_find_their_stored_tag() {
+        let stored = ["v0.9.0", "v1.0.0", "tool-2.0.0", "other-3.0.0", "nightly"];
+        let found = |wanted: &str| find_tag(stored, wanted, "tool");
+        assert_eq!(found("v1.0.0"), Some("v1.0.0"));
+        assert_eq!(found("1.0.0"), Some("v1.0.0"));
+        assert_eq!(found("1.0"), Some("v1.0.0"));
+        assert_eq!(found("2.0.0"), Some("tool-2.0.0"));
+        assert_eq!(found("nightly"), Some("nightly"));
+        // Tags of other tools and missing versions
+        assert_eq!(found("3.0.0"), None);
+        assert_eq!(found("1.1.0"), None);
+        // An exact tag wins over one for the same version
+        assert_eq!(find_tag(["v1.0.0", "1.0.0"], "1.0.0", "tool"), Some("1.0.0"));
+    }
+
+    #[test]
+    fn tool_prefixes() {
+        let tags = [("tool-v1.2.0", false), ("other-v9.0.0", false), ("tool-v1.3.0", false), ("v1.1.0", false)];
+        assert_eq!(selected(&tags, "tool", "^1").as_deref(), Some("tool-v1.3.0"));
+        assert_eq!(selected(&tags, "tool", "*").as_deref(), Some("tool-v1.3.0"));
+        assert_eq!(selected(&tags, "other", "*").as_deref(), Some("other-v9.0.0"));
+    }
+
+    #[test]
+    fn drafts_and_unversioned_tags_are_ignored() {
+        let mut releases = vec![release("v3.0.0", false), release("nightly", true), release("v2.0.0", false)];
+        releases[0].draft = true;
+        let found = select_release(releases, "tool", &VersionRequirement::LatestPrerelease);
+        assert_eq!(found.map(|r| r.tag_name).as_deref(), Some("v2.0.0"));
+    }
+}